@@ -1,9 +1,8 @@
-use intcode::{parse, run_program, ReturnStatus, State};
+use intcode::{parse, Cluster, ClusterReceiver, ClusterSender, InputPolicy, State};
 use std::cmp;
 use std::env;
 use std::fs::File;
 use std::io::{BufReader, Read};
-use std::mem;
 use std::path::Path;
 
 fn main() -> Result<(), String> {
@@ -35,47 +34,34 @@ fn read_file(path: &Path) -> std::io::Result<String> {
 }
 
 fn run_with_feedback_loop(prog: Vec<isize>, phase_settings: &[isize]) -> Result<isize, String> {
-    let mut processes: Vec<State> = Vec::with_capacity(phase_settings.len());
-
-    let mut output: Vec<isize> = vec![0];
-    let mut input: Vec<isize> = Vec::with_capacity(10);
-    let mut last_status = ReturnStatus::Wait;
-
-    // start processes with phase settings
-    for phase in phase_settings {
-        mem::swap(&mut input, &mut output);
-        input.insert(0, *phase);
-        output.clear();
-
-        let (state, status, o) = run_program(State::new(prog.clone()), &input)?;
-        processes.push(state);
-        last_status = status;
-        output = o;
+    let mut cluster = Cluster::new();
+    let (mut senders, receivers): (Vec<ClusterSender>, Vec<ClusterReceiver>) =
+        phase_settings.iter().map(|_| cluster.channel()).unzip();
+    for (phase, sender) in phase_settings.iter().zip(&senders) {
+        sender.send(*phase);
     }
-
-    while last_status != ReturnStatus::Halt {
-        for process in &mut processes {
-            mem::swap(&mut input, &mut output);
-            output.clear();
-
-            // There is probably a better way to do this so  processes[i].clone() is not
-            // necessary, but it is 1:15 am and I am tired.
-            let (state, status, o) = run_program(process.clone(), &input)?;
-            *process = state;
-            if last_status == ReturnStatus::Halt && status != ReturnStatus::Halt {
-                return Err(
-                    "Process is waiting, but input process has halted -> deadlock".to_owned(),
-                );
-            }
-            last_status = status;
-            output = o;
-        }
+    senders
+        .first()
+        .ok_or_else(|| "No phase settings given".to_owned())?
+        .send(0);
+
+    // every amplifier sends its output to the next one, the last one back to the first
+    senders.rotate_left(1);
+    for (input, output) in receivers.into_iter().zip(senders) {
+        cluster.add(
+            State::new(prog.clone()),
+            input,
+            Some(output),
+            InputPolicy::Blocking,
+        );
     }
 
-    output
-        .get(0)
-        .cloned()
-        .ok_or_else(|| "No output found".to_owned())
+    let mut signal = None;
+    for result in cluster.run() {
+        let (_, _, output) = result?;
+        signal = output.last().cloned();
+    }
+    signal.ok_or_else(|| "No output found".to_owned())
 }
 
 fn generate_possible_settings_chain() -> Vec<[isize; 5]> {
@@ -1,4 +1,4 @@
-use intcode::{parse, run_program, ReturnStatus, State};
+use intcode::{decode_ascii, encode_ascii, parse, run_program, ReturnStatus, State};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::env;
@@ -74,10 +74,7 @@ fn read_cam_image(program: Vec<isize>) -> Result<HashMap<Vec2, char>, String> {
     let mut col: usize = 0;
     let mut map: HashMap<Vec2, char> = HashMap::with_capacity(output.len());
 
-    for cell in output {
-        let cell_char: char = u32::try_from(cell)
-            .map_err(|e| e.to_string())
-            .and_then(|u| char::try_from(u).map_err(|e| e.to_string()))?;
+    for cell_char in decode_ascii(&output)?.text.chars() {
         if cell_char == '\n' {
             row += 1;
             col = 0;
@@ -123,19 +120,16 @@ fn program_and_run_robot(mut program: Vec<isize>) -> Result<isize, String> {
     program[0] = 2;
 
     // I still have no idea for an algorithm to figure out that movement program…
-    let move_logic: Vec<isize> =
-        "A,B,A,B,A,C,B,C,A,C\nL,6,R,12,L,6\nR,12,L,10,L,4,L,6\nL,10,L,10,L,4,L,6\nn\n"
-            .chars()
-            .filter_map(|c| u32::try_from(c).ok().and_then(|u| isize::try_from(u).ok()))
-            .collect();
+    let move_logic: Vec<isize> = encode_ascii(
+        "A,B,A,B,A,C,B,C,A,C\nL,6,R,12,L,6\nR,12,L,10,L,4,L,6\nL,10,L,10,L,4,L,6\nn\n",
+    )?;
 
     let (_, status, output) = run_program(State::new(program), &move_logic)?;
     if status != ReturnStatus::Halt {
         return Err("Robot did not exit with return status HALT".to_owned());
     }
 
-    output
-        .last()
-        .cloned()
-        .ok_or_else(|| "No output after running robot".to_owned())
+    decode_ascii(&output)?
+        .value
+        .ok_or_else(|| "No result after running robot".to_owned())
 }
@@ -1,4 +1,4 @@
-use intcode::{parse, run_program, ReturnStatus, State};
+use intcode::{parse, run_program_fast, ReturnStatus, State};
 use std::env;
 use std::fs::File;
 use std::io::{BufReader, Read};
@@ -38,7 +38,7 @@ fn read_file(path: &Path) -> std::io::Result<String> {
 }
 
 fn check_pos(program: Vec<isize>, x: isize, y: isize) -> Result<bool, String> {
-    let (_, status, output) = run_program(State::new(program), &[x, y])?;
+    let (_, status, output) = run_program_fast(State::new(program), &[x, y])?;
     if status != ReturnStatus::Halt {
         return Err("Program did not halt, it is waiting for more input".to_owned());
     }
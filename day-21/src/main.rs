@@ -1,5 +1,4 @@
-use intcode::{parse, run_program, ReturnStatus, State};
-use std::convert::TryFrom;
+use intcode::{decode_ascii, encode_ascii, parse, run_program, ReturnStatus, State};
 use std::env;
 use std::fs::File;
 use std::io::{BufReader, Read};
@@ -43,23 +42,19 @@ fn main() -> Result<(), String> {
 }
 
 fn run_springbot(program: Vec<isize>, springscript: &str) -> Result<isize, String> {
-    let input: Vec<isize> = springscript
-        .chars()
-        .filter_map(|c| u32::try_from(c).ok().and_then(|u| isize::try_from(u).ok()))
-        .collect();
+    let input: Vec<isize> = encode_ascii(springscript)?;
 
     let (_, status, output) = run_program(State::new(program), &input)?;
     if status != ReturnStatus::Halt {
         return Err("Program did not halt correctly".to_owned());
     }
 
-    let ostring: String = output
-        .iter()
-        .filter_map(|i| u32::try_from(*i).ok().and_then(|u| char::try_from(u).ok()))
-        .collect();
-    println!("{}", ostring);
+    let output = decode_ascii(&output)?;
+    println!("{}", output.text);
 
-    output.last().copied().ok_or_else(|| "No output".to_owned())
+    output
+        .value
+        .ok_or_else(|| "The springbot did not make it across".to_owned())
 }
 
 fn read_file(path: &Path) -> std::io::Result<String> {
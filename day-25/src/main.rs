@@ -1,6 +1,7 @@
-use intcode::{parse, run_program, ReturnStatus, State};
+use intcode::{
+    decode_ascii, encode_ascii, parse, replay, Recorder, ReturnStatus, State, Transcript,
+};
 use std::collections::HashMap;
-use std::convert::TryFrom;
 use std::env;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
@@ -14,7 +15,16 @@ fn main() -> Result<(), String> {
     let content = read_file(&Path::new(&filename)).map_err(|e| e.to_string())?;
     let program = parse(&content)?;
 
-    run_interactive(program)?;
+    // a transcript written with `$transcript` continues the recorded session
+    let transcript = match env::args().nth(2) {
+        Some(filename) => {
+            let text = read_file(Path::new(&filename)).map_err(|e| e.to_string())?;
+            Some(Transcript::from_text(&text)?)
+        }
+        None => None,
+    };
+
+    run_interactive(program, transcript)?;
 
     Ok(())
 }
@@ -35,6 +45,7 @@ enum Command {
     NativeDrop(String),
     NativeInv,
     Trace(String),
+    Transcript(String),
     Map,
 }
 
@@ -61,13 +72,25 @@ impl Display for Command {
             Command::NativeDrop(name) => write!(f, "drop {}", name),
             Command::NativeInv => write!(f, "inv"),
             Command::Trace(fname) => write!(f, "$trace {}", fname),
+            Command::Transcript(fname) => write!(f, "$transcript {}", fname),
             Command::Map => write!(f, "$map"),
         }
     }
 }
 
-fn run_interactive(program: Vec<isize>) -> Result<(), String> {
-    let mut process = State::new(program);
+fn run_interactive(program: Vec<isize>, transcript: Option<Transcript>) -> Result<(), String> {
+    let mut process = match transcript {
+        Some(transcript) => {
+            let mut state = State::new(program);
+            replay(&mut state, &transcript)?;
+            println!(
+                "Replayed {} transcript events, the room map starts here.",
+                transcript.events.len()
+            );
+            Recorder::with_transcript(state, transcript)
+        }
+        None => Recorder::new(State::new(program)),
+    };
     let mut input: String = String::new();
 
     let mut rooms: HashMap<(i32, i32), Room> = HashMap::with_capacity(32);
@@ -75,9 +98,18 @@ fn run_interactive(program: Vec<isize>) -> Result<(), String> {
     let mut pos: (i32, i32) = (0, 0);
 
     loop {
+        let encoded = match encode_ascii(&input) {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                // the game only understands ASCII, ask again instead of ending the session
+                print_error(&e.to_string());
+                input = read_input()?;
+                continue;
+            }
+        };
         let formal_input = parse_input(&input);
-        let (state, status, output_raw) = run_program(process, &str_to_intcode_input(&input))?;
-        process = state;
+        let mut output_raw: Vec<isize> = Vec::new();
+        let status = process.run(&mut &encoded[..], &mut output_raw)?;
         let output = parse_output(&output_raw)?;
 
         match formal_input {
@@ -99,6 +131,12 @@ fn run_interactive(program: Vec<isize>) -> Result<(), String> {
                     print_error(&format!("Error writing trace: {}", e));
                 }
             }
+            Some(Command::Transcript(ref filename)) => {
+                let text = process.transcript().to_text();
+                if let Err(e) = std::fs::write(Path::new(filename), text) {
+                    print_error(&format!("Error writing transcript: {}", e));
+                }
+            }
             Some(Command::Map) => {
                 print_map(&rooms, pos);
             }
@@ -133,14 +171,11 @@ fn print_error(error: &str) {
 }
 
 fn parse_output(output: &[isize]) -> Result<String, String> {
-    output
-        .iter()
-        .map(|i| {
-            u32::try_from(*i)
-                .map_err(|e| e.to_string())
-                .and_then(|u| char::try_from(u).map_err(|e| e.to_string()))
-        })
-        .collect()
+    let output = decode_ascii(output)?;
+    match output.value {
+        Some(value) => Err(format!("Output value {} is not ASCII", value)),
+        None => Ok(output.text),
+    }
 }
 
 fn read_input() -> Result<String, String> {
@@ -150,7 +185,9 @@ fn read_input() -> Result<String, String> {
 }
 
 fn parse_input(input: &str) -> Option<Command> {
-    if input.starts_with("$trace ") {
+    if let Some(filename) = input.strip_prefix("$transcript ") {
+        Some(Command::Transcript(filename.trim().to_owned()))
+    } else if input.starts_with("$trace ") {
         Some(Command::Trace(input["$trace ".len()..].trim().to_owned()))
     } else if input.trim() == "$map" {
         Some(Command::Map)
@@ -179,10 +216,6 @@ fn parse_door_command(input: &str) -> Option<Door> {
     }
 }
 
-fn str_to_intcode_input(input: &str) -> Vec<isize> {
-    input.chars().map(|c| c as isize).collect()
-}
-
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
 enum Door {
     North,
@@ -0,0 +1,417 @@
+// Static analysis of a program, without executing it.
+//
+// Starting at address 0, all instructions reachable through fall-through and jumps with
+// immediate targets are decoded. Jumps with targets from memory (position or relative mode) are
+// indirect: their targets are unknown, so code only reachable through them is reported as data.
+// Conditions in immediate mode are evaluated, e.g. `JNZ #1, #10` never falls through.
+use crate::{Instruction, IntcodeError, Mode, Opcode};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Severity {
+    // the program fails if execution reaches this point
+    Error,
+    // the program may work, but is hard to analyze
+    Warning,
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum IssueKind {
+    // the instruction can not be decoded or does not fit into the program
+    Invalid(IntcodeError),
+    // a jump with an immediate target leaves the program
+    JumpOutOfProgram { target: isize },
+    // execution continues behind the last word of the program
+    FallsOffEnd,
+    // the instruction writes to an address that is part of a reachable instruction
+    SelfModifyingWrite { target: usize },
+    // a jump to an address read from memory
+    IndirectJump,
+}
+
+// A finding of the analysis for the instruction at `address`
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct Issue {
+    pub address: usize,
+    pub kind: IssueKind,
+}
+
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            IssueKind::Invalid(_) | IssueKind::JumpOutOfProgram { .. } | IssueKind::FallsOffEnd => {
+                Severity::Error
+            }
+            IssueKind::SelfModifyingWrite { .. } => Severity::Warning,
+            IssueKind::IndirectJump => Severity::Note,
+        }
+    }
+}
+
+// Renders the issue in a lint-style format, e.g. "12: warning: writes to code at address 3"
+impl Display for Issue {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "{}: {}: ", self.address, self.severity())?;
+        match &self.kind {
+            IssueKind::Invalid(error) => write!(f, "{}", error),
+            IssueKind::JumpOutOfProgram { target } => {
+                write!(f, "jumps to {}, outside of the program", target)
+            }
+            IssueKind::FallsOffEnd => write!(f, "execution continues behind the program"),
+            IssueKind::SelfModifyingWrite { target } => {
+                write!(f, "writes to code at address {}", target)
+            }
+            IssueKind::IndirectJump => write!(f, "indirect jump, the target is not known"),
+        }
+    }
+}
+
+// A sequence of instructions that is only entered at its first instruction and only left
+// after its last one
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct Block {
+    pub start: usize,
+    // address behind the last instruction
+    pub end: usize,
+    // start addresses of the blocks execution can continue with
+    pub successors: Vec<usize>,
+    // the block ends with an indirect jump, so there may be more successors
+    pub indirect: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Analysis {
+    pub len: usize,
+    // all reachable instructions by address
+    pub instructions: BTreeMap<usize, Instruction>,
+    // the control-flow graph, blocks by start address
+    pub blocks: BTreeMap<usize, Block>,
+    // sorted by address
+    pub issues: Vec<Issue>,
+    // addresses covered by reachable instructions, including their parameters
+    code: BTreeSet<usize>,
+}
+
+impl Analysis {
+    pub fn is_code(&self, address: usize) -> bool {
+        self.code.contains(&address)
+    }
+
+    // number of addresses covered by reachable instructions
+    pub fn code_size(&self) -> usize {
+        self.code.len()
+    }
+
+    // ranges (start, end exclusive) of addresses that are not part of reachable instructions
+    pub fn data_ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for address in (0..self.len).filter(|a| !self.code.contains(a)) {
+            match ranges.last_mut() {
+                Some((_, end)) if *end == address => *end += 1,
+                _ => ranges.push((address, address + 1)),
+            }
+        }
+        ranges
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity() == severity)
+            .count()
+    }
+}
+
+// what can happen after an instruction
+struct Flow {
+    // addresses execution can continue at
+    next: Vec<usize>,
+    indirect: bool,
+}
+
+pub fn analyze(program: &[isize]) -> Analysis {
+    let len = program.len();
+    let mut instructions: BTreeMap<usize, Instruction> = BTreeMap::new();
+    let mut flows: BTreeMap<usize, Flow> = BTreeMap::new();
+    let mut issues: Vec<Issue> = Vec::new();
+    let mut writes: Vec<(usize, usize)> = Vec::new();
+    let mut jump_targets: BTreeSet<usize> = BTreeSet::new();
+    jump_targets.insert(0);
+
+    let mut todo: Vec<usize> = vec![0];
+    if len == 0 {
+        todo.clear();
+        issues.push(Issue {
+            address: 0,
+            kind: IssueKind::FallsOffEnd,
+        });
+    }
+    while let Some(address) = todo.pop() {
+        if instructions.contains_key(&address) {
+            continue;
+        }
+        let mut issue = |kind: IssueKind| issues.push(Issue { address, kind });
+        let instruction = match Instruction::decode(address, program[address]) {
+            Ok(instruction) => instruction,
+            Err(error) => {
+                issue(IssueKind::Invalid(error));
+                continue;
+            }
+        };
+        if address + instruction.size() > len {
+            issue(IssueKind::Invalid(IntcodeError::MissingOperands {
+                address,
+                mem_size: len,
+            }));
+            continue;
+        }
+        let param = |n: usize| (instruction.modes[n], program[address + n + 1]);
+
+        // writes to fixed addresses
+        let dest = match instruction.opcode {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq => Some(param(2)),
+            Opcode::In => Some(param(0)),
+            _ => None,
+        };
+        if let Some((mode, target)) = dest {
+            if mode != Mode::Relative && target >= 0 {
+                writes.push((address, target as usize));
+            }
+        }
+
+        let fall_through = address + instruction.size();
+        let mut flow = Flow {
+            next: Vec::with_capacity(2),
+            indirect: false,
+        };
+        match instruction.opcode {
+            Opcode::Hlt => (),
+            Opcode::Jnz | Opcode::Jz => {
+                let (condition_mode, condition) = param(0);
+                let (target_mode, target) = param(1);
+                // `None` if the condition is only known at runtime
+                let jumps = if condition_mode == Mode::Immediate {
+                    Some((condition != 0) == (instruction.opcode == Opcode::Jnz))
+                } else {
+                    None
+                };
+                if jumps != Some(true) {
+                    flow.next.push(fall_through);
+                }
+                if jumps != Some(false) {
+                    if target_mode != Mode::Immediate {
+                        flow.indirect = true;
+                        issue(IssueKind::IndirectJump);
+                    } else if target < 0 || target as usize >= len {
+                        issue(IssueKind::JumpOutOfProgram { target });
+                    } else {
+                        flow.next.push(target as usize);
+                        jump_targets.insert(target as usize);
+                    }
+                }
+            }
+            _ => flow.next.push(fall_through),
+        }
+        if flow.next.contains(&len) {
+            issue(IssueKind::FallsOffEnd);
+            flow.next.retain(|next| *next != len);
+        }
+        if instruction.opcode == Opcode::Jnz || instruction.opcode == Opcode::Jz {
+            jump_targets.extend(flow.next.iter().cloned());
+        }
+        todo.extend(flow.next.iter().cloned());
+        instructions.insert(address, instruction);
+        flows.insert(address, flow);
+    }
+
+    let code: BTreeSet<usize> = instructions
+        .iter()
+        .flat_map(|(address, instruction)| *address..*address + instruction.size())
+        .collect();
+    for (address, target) in writes {
+        if code.contains(&target) {
+            issues.push(Issue {
+                address,
+                kind: IssueKind::SelfModifyingWrite { target },
+            });
+        }
+    }
+    issues.sort();
+
+    let blocks = build_blocks(&instructions, &flows, &jump_targets);
+    Analysis {
+        len,
+        instructions,
+        blocks,
+        issues,
+        code,
+    }
+}
+
+// A block starts at a jump target or behind a jump and ends with a jump, a halt, an invalid
+// instruction or in front of another block.
+fn build_blocks(
+    instructions: &BTreeMap<usize, Instruction>,
+    flows: &BTreeMap<usize, Flow>,
+    leaders: &BTreeSet<usize>,
+) -> BTreeMap<usize, Block> {
+    let mut blocks: BTreeMap<usize, Block> = BTreeMap::new();
+    for start in leaders.iter().filter(|a| instructions.contains_key(a)) {
+        let mut address = *start;
+        loop {
+            let flow = &flows[&address];
+            let end = address + instructions[&address].size();
+            let continues = flow.next == [end] && !flow.indirect;
+            if !continues || leaders.contains(&end) || !instructions.contains_key(&end) {
+                let successors = flow
+                    .next
+                    .iter()
+                    .filter(|next| instructions.contains_key(next))
+                    .cloned()
+                    .collect();
+                blocks.insert(
+                    *start,
+                    Block {
+                        start: *start,
+                        end,
+                        successors,
+                        indirect: flow.indirect,
+                    },
+                );
+                break;
+            }
+            address = end;
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assemble;
+
+    fn analyze_source(source: &str) -> Analysis {
+        analyze(&assemble(source).expect("Expected valid source"))
+    }
+
+    #[test]
+    fn analyze_builds_control_flow_graph() {
+        // given
+        let source = "
+                    IN [n]
+            loop:   OUT [n]
+                    ADD [n], #-1, [n]
+                    JNZ [n], #loop
+                    HLT
+            n:      DATA 0, 12345
+        ";
+
+        // when
+        let analysis = analyze_source(source);
+
+        // then
+        let blocks: Vec<&Block> = analysis.blocks.values().collect();
+        assert_eq!(
+            blocks,
+            vec![
+                &Block {
+                    start: 0,
+                    end: 2,
+                    successors: vec![2],
+                    indirect: false
+                },
+                &Block {
+                    start: 2,
+                    end: 11,
+                    successors: vec![11, 2],
+                    indirect: false
+                },
+                &Block {
+                    start: 11,
+                    end: 12,
+                    successors: vec![],
+                    indirect: false
+                },
+            ]
+        );
+        assert!(analysis.issues.is_empty());
+        assert_eq!(analysis.data_ranges(), vec![(12, 14)]);
+        assert!(analysis.is_code(10));
+        assert!(!analysis.is_code(13));
+    }
+
+    #[test]
+    fn analyze_reports_invalid_code_only_on_reachable_paths() {
+        // given
+        let source = "
+                    JZ #0, #skip
+                    DATA 42, 1234
+            skip:   JNZ [x], #end
+            x:      DATA 77
+            end:    OUT rb+0
+        ";
+
+        // when
+        let analysis = analyze_source(source);
+
+        // then
+        let issues: Vec<String> = analysis.issues.iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            issues,
+            vec![
+                "8: error: Unknown opcode 77 at address 8",
+                "9: error: execution continues behind the program",
+            ]
+        );
+        assert_eq!(analysis.count(Severity::Error), 2);
+        assert_eq!(analysis.data_ranges(), vec![(3, 5), (8, 9)]);
+    }
+
+    #[test]
+    fn analyze_detects_self_modifying_writes_and_indirect_jumps() {
+        // given
+        let source = "
+                    ADD #1, #2, [target]
+                    JZ [n], rb+0
+                    JNZ #1, #target
+                    JNZ #1, #1000
+            target: HLT
+            n:      DATA 0
+        ";
+
+        // when
+        let analysis = analyze_source(source);
+
+        // then
+        let kinds: Vec<(usize, IssueKind)> = analysis
+            .issues
+            .iter()
+            .map(|i| (i.address, i.kind.clone()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (0, IssueKind::SelfModifyingWrite { target: 13 }),
+                (4, IssueKind::IndirectJump),
+            ]
+        );
+        assert_eq!(analysis.blocks[&0].successors, vec![7]);
+        assert!(analysis.blocks[&0].indirect);
+        assert_eq!(analysis.blocks[&7].successors, vec![13]);
+        assert_eq!(analysis.data_ranges(), vec![(10, 13), (14, 15)]);
+        assert_eq!(analysis.count(Severity::Note), 1);
+    }
+}
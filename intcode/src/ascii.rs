@@ -0,0 +1,298 @@
+// ASCII I/O for programs that talk in text (days 17, 21 and 25): every character is one value.
+//
+// Programs usually report their actual result as a single value after the text, outside of the
+// ASCII range (e.g. the hull damage on day 21). Decoding separates such a trailing value from the
+// text; a non-ASCII value anywhere else is an error.
+use crate::{Cell, DenseMemory, IntcodeError, Memory, ReturnStatus, State, StepEvent};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum AsciiError {
+    // a character of the input is not ASCII
+    NonAsciiInput { character: char },
+    // the value at `position` of the output is not ASCII and not the last value
+    NonAsciiOutput { position: usize, value: String },
+    Intcode(IntcodeError),
+}
+
+impl Display for AsciiError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            AsciiError::NonAsciiInput { character } => {
+                write!(f, "Input character {:?} is not ASCII", character)
+            }
+            AsciiError::NonAsciiOutput { position, value } => write!(
+                f,
+                "Output value {} at position {} is not an ASCII character",
+                value, position
+            ),
+            AsciiError::Intcode(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for AsciiError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AsciiError::Intcode(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<IntcodeError> for AsciiError {
+    fn from(error: IntcodeError) -> AsciiError {
+        AsciiError::Intcode(error)
+    }
+}
+
+impl From<AsciiError> for String {
+    fn from(error: AsciiError) -> String {
+        error.to_string()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
+pub struct AsciiOutput<C = isize> {
+    pub text: String,
+    // the non-ASCII value after the text, if any
+    pub value: Option<C>,
+}
+
+pub fn encode_ascii<C: Cell>(text: &str) -> Result<Vec<C>, AsciiError> {
+    text.chars()
+        .map(|character| {
+            if character.is_ascii() {
+                Ok(C::from_isize(character as isize))
+            } else {
+                Err(AsciiError::NonAsciiInput { character })
+            }
+        })
+        .collect()
+}
+
+pub fn decode_ascii<C: Cell>(output: &[C]) -> Result<AsciiOutput<C>, AsciiError> {
+    let mut result = AsciiOutput {
+        text: String::with_capacity(output.len()),
+        value: None,
+    };
+    for (position, value) in output.iter().enumerate() {
+        match ascii_char(value) {
+            Some(character) => result.text.push(character),
+            None if position + 1 == output.len() => result.value = Some(value.clone()),
+            None => {
+                return Err(AsciiError::NonAsciiOutput {
+                    position,
+                    value: value.to_string(),
+                })
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn ascii_char<C: Cell>(value: &C) -> Option<char> {
+    value
+        .to_isize()
+        .and_then(|value| u8::try_from(value).ok())
+        .filter(u8::is_ascii)
+        .map(char::from)
+}
+
+// A machine that is fed with text and read up to a prompt or line end.
+//
+// Input is queued and only consumed when the program reads it. Output that was produced after
+// the delimiter of a read stays buffered for the next read.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct AsciiMachine<M: Memory = DenseMemory> {
+    state: State<M>,
+    input: VecDeque<M::Cell>,
+    output: Vec<M::Cell>,
+    // why the machine stopped during the last read, `None` if it did not stop
+    status: Option<ReturnStatus>,
+}
+
+impl<M: Memory> AsciiMachine<M> {
+    pub fn new(state: State<M>) -> AsciiMachine<M> {
+        AsciiMachine {
+            state,
+            input: VecDeque::new(),
+            output: Vec::new(),
+            status: None,
+        }
+    }
+
+    pub fn state(&self) -> &State<M> {
+        &self.state
+    }
+
+    pub fn into_state(self) -> State<M> {
+        self.state
+    }
+
+    // `Some(ReturnStatus::Wait)` if the last read stopped because the program needs more input,
+    // `Some(ReturnStatus::Halt)` if it halted
+    pub fn status(&self) -> Option<ReturnStatus> {
+        self.status.clone()
+    }
+
+    // Queue the text as input. Nothing is queued if it contains a non-ASCII character.
+    pub fn send(&mut self, text: &str) -> Result<(), AsciiError> {
+        let values = encode_ascii(text)?;
+        self.input.extend(values);
+        Ok(())
+    }
+
+    // Queue the line and a newline as input
+    pub fn send_line(&mut self, line: &str) -> Result<(), AsciiError> {
+        self.send(line)?;
+        self.input.push_back(M::Cell::from_isize('\n' as isize));
+        Ok(())
+    }
+
+    // Run until the output ends with `delimiter` (e.g. a prompt like "Command?\n") or the machine
+    // stops. Returns the output up to and including the delimiter.
+    pub fn read_until(&mut self, delimiter: &str) -> Result<AsciiOutput<M::Cell>, AsciiError> {
+        let delimiter: Vec<M::Cell> = encode_ascii(delimiter)?;
+        self.status = None;
+        let mut searched = 0;
+        loop {
+            if let Some(end) = find(&self.output[searched..], &delimiter) {
+                let end = searched + end + delimiter.len();
+                let rest = self.output.split_off(end);
+                return decode_ascii(&std::mem::replace(&mut self.output, rest));
+            }
+            // only a match that ends with the next output value can be new
+            searched = (self.output.len() + 1)
+                .saturating_sub(delimiter.len())
+                .min(self.output.len());
+            if let Some(status) = self.step()? {
+                return self.stop(status);
+            }
+        }
+    }
+
+    // Run until a complete line was written or the machine stops. The newline is part of the
+    // returned text.
+    pub fn read_line(&mut self) -> Result<AsciiOutput<M::Cell>, AsciiError> {
+        self.read_until("\n")
+    }
+
+    // Run until the machine needs more input or halts and return all output
+    pub fn read_all(&mut self) -> Result<AsciiOutput<M::Cell>, AsciiError> {
+        self.status = None;
+        loop {
+            if let Some(status) = self.step()? {
+                return self.stop(status);
+            }
+        }
+    }
+
+    // execute one instruction, returns why the machine stopped
+    fn step(&mut self) -> Result<Option<ReturnStatus>, IntcodeError> {
+        match self.state.step_with(&mut self.input)?.event {
+            StepEvent::Output(value) => self.output.push(value),
+            StepEvent::InputRequired => return Ok(Some(ReturnStatus::Wait)),
+            StepEvent::Halt => return Ok(Some(ReturnStatus::Halt)),
+            StepEvent::Continue | StepEvent::Input(_) => (),
+        }
+        Ok(None)
+    }
+
+    fn stop(&mut self, status: ReturnStatus) -> Result<AsciiOutput<M::Cell>, AsciiError> {
+        self.status = Some(status);
+        decode_ascii(&std::mem::take(&mut self.output))
+    }
+}
+
+// start of the first occurrence of `needle`; an empty needle never matches, so reading up to
+// an empty delimiter reads everything
+fn find<C: Eq>(haystack: &[C], needle: &[C]) -> Option<usize> {
+    if needle.is_empty() {
+        return None;
+    }
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assemble;
+
+    // prints a prompt, echoes a line and outputs 1000
+    const ECHO: &str = "
+                OUT #63
+                OUT #10
+        loop:   IN [c]
+                OUT [c]
+                EQ [c], #10, [t]
+                JZ [t], #loop
+                OUT #1000
+                HLT
+        c:      DATA 0
+        t:      DATA 0
+    ";
+
+    #[test]
+    fn ascii_machine_reads_up_to_prompt_and_separates_the_result() {
+        // given
+        let program = assemble(ECHO).expect("Expected valid program");
+        let mut machine = AsciiMachine::new(State::new(program));
+
+        // when
+        let prompt = machine.read_until("?").expect("Expected prompt");
+        let newline = machine.read_line().expect("Expected newline");
+        let waiting = machine.read_line().expect("Expected valid run");
+        let status_waiting = machine.status();
+        machine.send_line("hi").expect("Expected ASCII input");
+        let echo = machine.read_line().expect("Expected echo");
+        let rest = machine.read_all().expect("Expected result");
+
+        // then
+        assert_eq!(prompt.text, "?");
+        assert_eq!(newline.text, "\n");
+        assert_eq!(waiting, AsciiOutput::default());
+        assert_eq!(status_waiting, Some(ReturnStatus::Wait));
+        assert_eq!(echo.text, "hi\n");
+        assert_eq!(
+            rest,
+            AsciiOutput {
+                text: String::new(),
+                value: Some(1000)
+            }
+        );
+        assert_eq!(machine.status(), Some(ReturnStatus::Halt));
+    }
+
+    #[test]
+    fn invalid_code_points_are_errors() {
+        // given
+        let output: Vec<isize> = vec![104, 105, -1, 10, 42];
+
+        // when
+        let decoded = decode_ascii(&output);
+        let encoded = encode_ascii::<isize>("Größe");
+
+        // then
+        assert_eq!(
+            decoded,
+            Err(AsciiError::NonAsciiOutput {
+                position: 2,
+                value: "-1".to_owned()
+            })
+        );
+        assert_eq!(encoded, Err(AsciiError::NonAsciiInput { character: 'ö' }));
+        assert_eq!(
+            decode_ascii(&output[3..]),
+            Ok(AsciiOutput {
+                text: "\n*".to_owned(),
+                value: None
+            })
+        );
+    }
+}
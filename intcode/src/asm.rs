@@ -0,0 +1,362 @@
+// Assembler for a human-readable intcode syntax.
+//
+// Syntax (one statement per line, mnemonics are case-insensitive):
+//
+//     ; comments start with a semicolon
+//     start:  IN [x]                  ; labels end with a colon
+//             ADD [x], #-1, rb+3      ; [..] position mode, #.. immediate mode, rb±.. relative
+//             JNZ [x], #start         ; labels can be used wherever a number is expected
+//             HLT
+//     x:      DATA 0, 1, end-x        ; raw words, simple sums and differences are allowed
+//     end:
+//
+// A number followed by a colon (e.g. `12:`) does not define a label, it asserts that the next
+// statement starts at that address. Together with the comments, this makes the listings of the
+// disassembler valid assembler input.
+use crate::{Instruction, Mode, Opcode};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct AsmError {
+    // line number, starting at 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+impl From<AsmError> for String {
+    fn from(error: AsmError) -> String {
+        error.to_string()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Statement<'a> {
+    Instruction(Opcode, Vec<(Mode, &'a str)>),
+    Data(Vec<&'a str>),
+}
+
+impl<'a> Statement<'a> {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction(opcode, _) => opcode.param_count() + 1,
+            Statement::Data(values) => values.len(),
+        }
+    }
+}
+
+// Assemble the source into a program that can be executed (or written in the comma-separated
+// format with `to_intcode_string`)
+pub fn assemble(source: &str) -> Result<Vec<isize>, AsmError> {
+    // first pass: parse statements and calculate the address of every label
+    let mut labels: HashMap<&str, isize> = HashMap::new();
+    let mut statements: Vec<(usize, Statement)> = Vec::new();
+    let mut address: usize = 0;
+    for (i, raw_line) in source.lines().enumerate() {
+        let line_nr = i + 1;
+        let error = |message: String| AsmError {
+            line: line_nr,
+            message,
+        };
+        let mut line = raw_line.split(';').next().unwrap_or("").trim();
+
+        while let Some((label, rest)) = split_label(line) {
+            if let Ok(expected) = label.parse::<usize>() {
+                if expected != address {
+                    return Err(error(format!(
+                        "address assertion failed: expected address {}, but current address is {}",
+                        expected, address
+                    )));
+                }
+            } else if !is_identifier(label) {
+                return Err(error(format!("invalid label name '{}'", label)));
+            } else if labels.insert(label, address as isize).is_some() {
+                return Err(error(format!("duplicate label '{}'", label)));
+            }
+            line = rest.trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let statement = parse_statement(line).map_err(error)?;
+        address += statement.size();
+        statements.push((line_nr, statement));
+    }
+
+    // second pass: resolve expressions and encode the instructions
+    let mut program: Vec<isize> = Vec::with_capacity(address);
+    for (line_nr, statement) in statements {
+        let error = |message: String| AsmError {
+            line: line_nr,
+            message,
+        };
+        match statement {
+            Statement::Instruction(opcode, params) => {
+                let mut modes = [Mode::Position; 3];
+                for (mode, (param_mode, _)) in modes.iter_mut().zip(&params) {
+                    *mode = *param_mode;
+                }
+                program.push(Instruction { opcode, modes }.encode());
+                for (_, expression) in params {
+                    program.push(evaluate(expression, &labels).map_err(error)?);
+                }
+            }
+            Statement::Data(values) => {
+                for expression in values {
+                    program.push(evaluate(expression, &labels).map_err(error)?);
+                }
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+// format a program in the comma-separated format `parse` reads
+pub fn to_intcode_string(program: &[isize]) -> String {
+    let words: Vec<String> = program.iter().map(|w| w.to_string()).collect();
+    words.join(",")
+}
+
+fn split_label(line: &str) -> Option<(&str, &str)> {
+    let colon = line.find(':')?;
+    Some((line[..colon].trim(), &line[colon + 1..]))
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !s.eq_ignore_ascii_case("rb")
+}
+
+fn parse_statement(line: &str) -> Result<Statement<'_>, String> {
+    let (mnemonic, rest) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, ""),
+    };
+    let operands: Vec<&str> = if rest.is_empty() {
+        vec![]
+    } else {
+        rest.split(',').map(|s| s.trim()).collect()
+    };
+
+    if mnemonic.eq_ignore_ascii_case("data") {
+        if operands.is_empty() {
+            return Err("DATA needs at least one value".to_owned());
+        }
+        return Ok(Statement::Data(operands));
+    }
+
+    let opcode = Opcode::ALL
+        .iter()
+        .find(|opcode| opcode.mnemonic().eq_ignore_ascii_case(mnemonic))
+        .cloned()
+        .ok_or_else(|| format!("unknown mnemonic '{}'", mnemonic))?;
+
+    if operands.len() != opcode.param_count() {
+        return Err(format!(
+            "{} expects {} parameters, but got {}",
+            opcode.mnemonic(),
+            opcode.param_count(),
+            operands.len()
+        ));
+    }
+    let params = operands
+        .into_iter()
+        .map(parse_param)
+        .collect::<Result<Vec<(Mode, &str)>, String>>()?;
+    Ok(Statement::Instruction(opcode, params))
+}
+
+// split a parameter into its mode and the expression of its value
+fn parse_param(param: &str) -> Result<(Mode, &str), String> {
+    if let Some(expression) = param.strip_prefix('#') {
+        Ok((Mode::Immediate, expression.trim()))
+    } else if param.starts_with('[') && param.ends_with(']') {
+        Ok((Mode::Position, param[1..param.len() - 1].trim()))
+    } else if param.get(..2).map(|p| p.eq_ignore_ascii_case("rb")) == Some(true) {
+        let offset = param[2..].trim();
+        if offset.is_empty() {
+            Ok((Mode::Relative, "0"))
+        } else if offset.starts_with('+') || offset.starts_with('-') {
+            Ok((Mode::Relative, offset))
+        } else {
+            Err(format!("invalid relative parameter '{}'", param))
+        }
+    } else {
+        Err(format!(
+            "parameter '{}' has no mode, use [..], #.. or rb+..",
+            param
+        ))
+    }
+}
+
+// evaluate a sum or difference of numbers and labels, e.g. `-3`, `loop`, `x+2` or `end-start`
+fn evaluate(expression: &str, labels: &HashMap<&str, isize>) -> Result<isize, String> {
+    let overflow = || format!("expression '{}' overflows", expression);
+    let mut result: isize = 0;
+    let mut rest = expression.trim();
+    let mut negative = false;
+    if let Some(r) = rest.strip_prefix('+') {
+        rest = r;
+    } else if let Some(r) = rest.strip_prefix('-') {
+        rest = r;
+        negative = true;
+    }
+    loop {
+        let end = rest.find(['+', '-']).unwrap_or(rest.len());
+        let term = rest[..end].trim();
+        let value = if term.chars().next().map(|c| c.is_ascii_digit()) == Some(true) {
+            // parsed together with the sign, so that the lowest value can be written
+            let number = if negative {
+                format!("-{}", term)
+            } else {
+                term.to_owned()
+            };
+            number
+                .parse::<isize>()
+                .map_err(|e| format!("invalid number '{}': {}", number, e))?
+        } else if is_identifier(term) {
+            let value = *labels
+                .get(term)
+                .ok_or_else(|| format!("unknown label '{}'", term))?;
+            if negative {
+                value.checked_neg().ok_or_else(overflow)?
+            } else {
+                value
+            }
+        } else {
+            return Err(format!("invalid expression '{}'", expression));
+        };
+        result = result.checked_add(value).ok_or_else(overflow)?;
+        if end == rest.len() {
+            return Ok(result);
+        }
+        negative = rest[end..].starts_with('-');
+        rest = &rest[end + 1..];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{listing, run_program, State};
+
+    #[test]
+    fn assemble_encodes_modes_and_labels() {
+        // given
+        let source = "
+            ; read a number and print it twice
+            start:  IN [x]
+                    mul [x], #2, rb+3
+                    ARB rb-1
+                    JZ #0, #end
+            x:      DATA 7, end-x
+            end:    hlt
+        ";
+
+        // when
+        let program = assemble(source).expect("Expected valid source");
+
+        // then
+        assert_eq!(
+            program,
+            vec![3, 11, 21_002, 11, 2, 3, 209, -1, 1_106, 0, 13, 7, 2, 99]
+        );
+    }
+
+    #[test]
+    fn assemble_reports_line_numbers() {
+        assert_eq!(
+            assemble("HLT\n\nADD [1], #2\n"),
+            Err(AsmError {
+                line: 3,
+                message: "ADD expects 3 parameters, but got 2".to_owned()
+            })
+        );
+        assert_eq!(assemble("OUT [x]\nHLT").map_err(|e| e.line), Err(1));
+        assert_eq!(assemble("a: HLT\na: HLT").map_err(|e| e.line), Err(2));
+        assert_eq!(assemble("MOV [1], [2]").map_err(|e| e.line), Err(1));
+        assert_eq!(assemble("OUT 5").map_err(|e| e.line), Err(1));
+        assert_eq!(assemble("HLT\n0: HLT").map_err(|e| e.line), Err(2));
+    }
+
+    #[test]
+    fn assemble_handles_the_limits_of_isize() {
+        // given
+        let min = format!("DATA {}", isize::MIN);
+        let too_large = format!("HLT\nDATA {}+1", isize::MAX);
+
+        // when
+        let result1 = assemble(&min);
+        let result2 = assemble(&too_large);
+
+        // then
+        assert_eq!(result1, Ok(vec![isize::MIN]));
+        assert_eq!(
+            result2,
+            Err(AsmError {
+                line: 2,
+                message: format!("expression '{}+1' overflows", isize::MAX)
+            })
+        );
+        assert_eq!(
+            assemble(&listing(&[isize::MIN, 99])),
+            Ok(vec![isize::MIN, 99])
+        );
+    }
+
+    #[test]
+    fn assemble_reads_disassembler_listing() {
+        // given
+        let program = vec![
+            3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9, 21_201, -1, 3, 4, 204, -2, 42,
+        ];
+
+        // when
+        let reassembled = assemble(&listing(&program)).expect("Expected valid listing");
+
+        // then
+        assert_eq!(reassembled, program);
+    }
+
+    #[test]
+    fn assembled_program_runs() {
+        // given
+        let source = "
+                    IN [n]
+            loop:   OUT [n]
+                    ADD [n], #-1, [n]
+                    JNZ [n], #loop
+                    HLT
+            n:      DATA 0
+        ";
+        let program = assemble(source).expect("Expected valid source");
+
+        // when
+        let (_, _, output) =
+            run_program(State::new(program), &[3]).expect("Expected successful run");
+
+        // then
+        assert_eq!(output, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn to_intcode_string_creates_parseable_output() {
+        assert_eq!(to_intcode_string(&[1_002, 4, -3, 4, 99]), "1002,4,-3,4,99");
+    }
+}
@@ -0,0 +1,308 @@
+// An async adapter around the machine, independent of any runtime: only `std::task` is used, so
+// the futures can be run by any executor.
+//
+// A machine awaits input from an `AsyncInput` (like `futures::Stream`) and sends its output to
+// an `AsyncOutput` (like `futures::Sink`). Connected with `channel`, several machines form a
+// network in ordinary async code, e.g. the amplifier loop of day 7 or the network of day 23.
+use crate::{IntcodeError, Memory, ReturnStatus, State, StepEvent};
+use std::collections::VecDeque;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+// Number of instructions a machine executes before it gives other tasks a chance to run
+const INSTRUCTIONS_PER_YIELD: u32 = 1_000;
+
+pub trait AsyncInput<C = isize> {
+    // The next input value, `Poll::Ready(None)` if there will be no more input.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<C>>;
+}
+
+pub trait AsyncOutput<C = isize> {
+    // `Poll::Ready` once the output can take the next value
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()>;
+
+    // only called after `poll_ready` returned `Poll::Ready`
+    fn send(self: Pin<&mut Self>, value: C);
+}
+
+// values that are available right away; the input ends when the queue is empty
+impl<C: Unpin> AsyncInput<C> for VecDeque<C> {
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<C>> {
+        Poll::Ready(self.get_mut().pop_front())
+    }
+}
+
+impl<C: Unpin> AsyncOutput<C> for Vec<C> {
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Ready(())
+    }
+
+    fn send(self: Pin<&mut Self>, value: C) {
+        self.get_mut().push(value);
+    }
+}
+
+// Run the program in `state` until it halts, or until it needs input and `input` has ended
+// (`ReturnStatus::Wait`). Waiting for input or for the output to be ready does not block the
+// thread, and the machine yields regularly, so long computations do not starve other tasks.
+pub async fn run_async<M, I, O>(
+    state: &mut State<M>,
+    input: &mut I,
+    output: &mut O,
+) -> Result<ReturnStatus, IntcodeError>
+where
+    M: Memory,
+    I: AsyncInput<M::Cell> + Unpin + ?Sized,
+    O: AsyncOutput<M::Cell> + Unpin + ?Sized,
+{
+    let mut next_input: Option<M::Cell> = None;
+    let mut executed: u32 = 0;
+    loop {
+        match state.step_with(&mut next_input)?.event {
+            StepEvent::Continue | StepEvent::Input(_) => (),
+            StepEvent::Output(value) => {
+                poll_fn(|cx| Pin::new(&mut *output).poll_ready(cx)).await;
+                Pin::new(&mut *output).send(value);
+            }
+            StepEvent::InputRequired => {
+                match poll_fn(|cx| Pin::new(&mut *input).poll_next(cx)).await {
+                    Some(value) => next_input = Some(value),
+                    None => return Ok(ReturnStatus::Wait),
+                }
+            }
+            StepEvent::Halt => return Ok(ReturnStatus::Halt),
+        }
+        executed += 1;
+        if executed == INSTRUCTIONS_PER_YIELD {
+            executed = 0;
+            YieldNow(false).await;
+        }
+    }
+}
+
+// returns `Poll::Pending` once, after waking its task
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+struct Channel<C> {
+    queue: VecDeque<C>,
+    // the task waiting for the next value
+    waker: Option<Waker>,
+    senders: usize,
+}
+
+// An unbounded channel to connect machines. The receiver ends when all senders were dropped and
+// the queue is empty. Both ends can be used from different threads.
+pub fn channel<C>() -> (Sender<C>, Receiver<C>) {
+    let channel = Arc::new(Mutex::new(Channel {
+        queue: VecDeque::new(),
+        waker: None,
+        senders: 1,
+    }));
+    (
+        Sender {
+            channel: channel.clone(),
+        },
+        Receiver { channel },
+    )
+}
+
+pub struct Sender<C = isize> {
+    channel: Arc<Mutex<Channel<C>>>,
+}
+
+impl<C> Sender<C> {
+    // Send a value without waiting, this always works for unbounded channels.
+    pub fn send_now(&self, value: C) {
+        let mut channel = self.channel.lock().expect("Channel lock poisoned");
+        channel.queue.push_back(value);
+        if let Some(waker) = channel.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<C> Clone for Sender<C> {
+    fn clone(&self) -> Sender<C> {
+        self.channel.lock().expect("Channel lock poisoned").senders += 1;
+        Sender {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<C> Drop for Sender<C> {
+    fn drop(&mut self) {
+        // a poisoned lock is ignored: there is nobody left to notify properly
+        if let Ok(mut channel) = self.channel.lock() {
+            channel.senders -= 1;
+            if channel.senders == 0 {
+                if let Some(waker) = channel.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+impl<C> AsyncOutput<C> for Sender<C> {
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Ready(())
+    }
+
+    fn send(self: Pin<&mut Self>, value: C) {
+        self.send_now(value);
+    }
+}
+
+pub struct Receiver<C = isize> {
+    channel: Arc<Mutex<Channel<C>>>,
+}
+
+impl<C> AsyncInput<C> for Receiver<C> {
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<C>> {
+        let mut channel = self.channel.lock().expect("Channel lock poisoned");
+        match channel.queue.pop_front() {
+            Some(value) => Poll::Ready(Some(value)),
+            None if channel.senders == 0 => Poll::Ready(None),
+            None => {
+                channel.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::Wake;
+
+    type Task<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    // A minimal single-threaded executor: polls every task whose waker was called, until all
+    // tasks are done. Panics if no task is woken anymore, i.e. on a deadlock.
+    fn run_all<T>(tasks: Vec<Task<T>>) -> Vec<T> {
+        let mut tasks: Vec<(Task<T>, Arc<Flag>)> = tasks
+            .into_iter()
+            .map(|task| (task, Arc::new(Flag(AtomicBool::new(true)))))
+            .collect();
+        let mut results: Vec<Option<T>> = tasks.iter().map(|_| None).collect();
+        while results.iter().any(|result| result.is_none()) {
+            let mut progress = false;
+            for (i, (task, flag)) in tasks.iter_mut().enumerate() {
+                if results[i].is_some() || !flag.0.swap(false, Ordering::SeqCst) {
+                    continue;
+                }
+                progress = true;
+                let waker = Waker::from(flag.clone());
+                if let Poll::Ready(result) = task.as_mut().poll(&mut Context::from_waker(&waker)) {
+                    results[i] = Some(result);
+                }
+            }
+            assert!(progress, "deadlock: all tasks are waiting");
+        }
+        results.into_iter().flatten().collect()
+    }
+
+    #[test]
+    fn run_async_uses_queued_input_and_collects_output() {
+        // given
+        let mut state = State::new(vec![3, 9, 4, 9, 1005, 9, 0, 99, 0, 0]);
+        let mut input: VecDeque<isize> = vec![5, 2, 0, 7].into();
+        let mut output: Vec<isize> = Vec::new();
+
+        // when
+        let results = run_all(vec![Box::pin(run_async(
+            &mut state,
+            &mut input,
+            &mut output,
+        ))]);
+
+        // then
+        assert_eq!(results, vec![Ok(ReturnStatus::Halt)]);
+        assert_eq!(output, vec![5, 2, 0]);
+        assert_eq!(input, vec![7]);
+    }
+
+    // passes values on to a channel and remembers the last one
+    struct Recording {
+        sender: Sender,
+        last: Option<isize>,
+    }
+
+    impl AsyncOutput for Recording {
+        fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            Pin::new(&mut self.get_mut().sender).poll_ready(cx)
+        }
+
+        fn send(self: Pin<&mut Self>, value: isize) {
+            let recording = self.get_mut();
+            recording.last = Some(value);
+            recording.sender.send_now(value);
+        }
+    }
+
+    #[test]
+    fn machines_connected_by_channels_run_the_day_7_feedback_loop() {
+        // given
+        let program = vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let phases = [9, 8, 7, 6, 5];
+        let (mut senders, receivers): (Vec<Sender>, Vec<Receiver>) =
+            phases.iter().map(|_| channel()).unzip();
+        for (phase, sender) in phases.iter().zip(&senders) {
+            sender.send_now(*phase);
+        }
+        senders[0].send_now(0);
+        // amplifier n reads from channel n and writes to channel n + 1, the last one to 0
+        senders.rotate_left(1);
+
+        // when
+        let tasks: Vec<Task<_>> = receivers
+            .into_iter()
+            .zip(senders)
+            .map(|(mut input, sender)| {
+                let mut state = State::new(program.clone());
+                let task: Task<_> = Box::pin(async move {
+                    let mut output = Recording { sender, last: None };
+                    let result = run_async(&mut state, &mut input, &mut output).await;
+                    (result, output.last)
+                });
+                task
+            })
+            .collect();
+        let results: Vec<(Result<ReturnStatus, IntcodeError>, Option<isize>)> = run_all(tasks);
+
+        // then
+        assert!(results
+            .iter()
+            .all(|(result, _)| *result == Ok(ReturnStatus::Halt)));
+        assert_eq!(results[4].1, Some(139629729));
+    }
+}
@@ -0,0 +1,290 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, Mul};
+use std::str::FromStr;
+
+// A simple arbitrary-precision signed integer, just enough for intcode arithmetic.
+// The magnitude is stored in base 2^32, least significant digit first, without trailing zeros.
+// Zero is never negative, so derived equality and hashing work on the representation.
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+const BASE: u64 = 1 << 32;
+
+impl BigInt {
+    pub fn zero() -> BigInt {
+        BigInt::default()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn from_i128(value: i128) -> BigInt {
+        let mut magnitude = value.unsigned_abs();
+        let mut digits = Vec::with_capacity(4);
+        while magnitude != 0 {
+            digits.push(magnitude as u32);
+            magnitude >>= 32;
+        }
+        BigInt {
+            negative: value < 0,
+            digits,
+        }
+    }
+
+    pub fn to_i128(&self) -> Option<i128> {
+        if self.digits.len() > 4 {
+            return None;
+        }
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0u128, |acc, digit| (acc << 32) | *digit as u128);
+        if self.negative {
+            if magnitude <= i128::MAX as u128 + 1 {
+                Some((magnitude as i128).wrapping_neg())
+            } else {
+                None
+            }
+        } else {
+            i128::try_from(magnitude).ok()
+        }
+    }
+
+    fn from_parts(negative: bool, mut digits: Vec<u32>) -> BigInt {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigInt {
+            negative: negative && !digits.is_empty(),
+            digits,
+        }
+    }
+
+    // divide the magnitude by a small divisor in place, return the remainder
+    fn div_rem_small(digits: &mut [u32], divisor: u32) -> u32 {
+        let mut remainder: u64 = 0;
+        for digit in digits.iter_mut().rev() {
+            let current = (remainder << 32) | *digit as u64;
+            *digit = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        remainder as u32
+    }
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut result = Vec::with_capacity(long.len() + 1);
+    let mut carry: u64 = 0;
+    for (i, digit) in long.iter().enumerate() {
+        let current = *digit as u64 + short.get(i).cloned().unwrap_or(0) as u64 + carry;
+        result.push(current as u32);
+        carry = current >> 32;
+    }
+    if carry != 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+// a - b, requires |a| >= |b|
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow: u64 = 0;
+    for (i, digit) in a.iter().enumerate() {
+        let subtrahend = b.get(i).cloned().unwrap_or(0) as u64 + borrow;
+        let minuend = *digit as u64;
+        if minuend >= subtrahend {
+            result.push((minuend - subtrahend) as u32);
+            borrow = 0;
+        } else {
+            result.push((minuend + BASE - subtrahend) as u32);
+            borrow = 1;
+        }
+    }
+    result
+}
+
+fn cmp_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+impl Add<&BigInt> for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            BigInt::from_parts(self.negative, add_magnitudes(&self.digits, &other.digits))
+        } else {
+            match cmp_magnitudes(&self.digits, &other.digits) {
+                Ordering::Less => {
+                    BigInt::from_parts(other.negative, sub_magnitudes(&other.digits, &self.digits))
+                }
+                _ => BigInt::from_parts(self.negative, sub_magnitudes(&self.digits, &other.digits)),
+            }
+        }
+    }
+}
+
+impl Mul<&BigInt> for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        let mut result = vec![0u32; self.digits.len() + other.digits.len()];
+        for (i, a) in self.digits.iter().enumerate() {
+            let mut carry: u64 = 0;
+            for (j, b) in other.digits.iter().enumerate() {
+                let current = result[i + j] as u64 + *a as u64 * *b as u64 + carry;
+                result[i + j] = current as u32;
+                carry = current >> 32;
+            }
+            result[i + other.digits.len()] = carry as u32;
+        }
+        BigInt::from_parts(self.negative != other.negative, result)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitudes(&self.digits, &other.digits),
+            (true, true) => cmp_magnitudes(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<isize> for BigInt {
+    fn from(value: isize) -> BigInt {
+        BigInt::from_i128(value as i128)
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // split into chunks of 9 decimal digits, least significant first
+        let mut digits = self.digits.clone();
+        let mut chunks: Vec<u32> = Vec::new();
+        while !digits.is_empty() {
+            chunks.push(BigInt::div_rem_small(&mut digits, 1_000_000_000));
+            while digits.last() == Some(&0) {
+                digits.pop();
+            }
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        if let Some(first) = chunks.next() {
+            write!(f, "{}", first)?;
+        }
+        for chunk in chunks {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BigInt, String> {
+        let (negative, number) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("invalid digit found in string '{}'", s));
+        }
+        let ten = BigInt::from_i128(10);
+        let result = number.bytes().fold(BigInt::zero(), |acc, b| {
+            &(&acc * &ten) + &BigInt::from_i128((b - b'0') as i128)
+        });
+        Ok(BigInt::from_parts(negative, result.digits))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        s.parse().expect("Expected valid number")
+    }
+
+    #[test]
+    fn parse_and_display_round_trip() {
+        for s in &[
+            "0",
+            "1",
+            "-1",
+            "4294967296",
+            "-18446744073709551616",
+            "123456789012345678901234567890",
+            "1000000000",
+        ] {
+            assert_eq!(big(s).to_string(), *s);
+        }
+        assert_eq!(big("-0"), BigInt::zero());
+        assert!("12a".parse::<BigInt>().is_err());
+        assert!("-".parse::<BigInt>().is_err());
+    }
+
+    #[test]
+    fn add_works_for_all_signs() {
+        assert_eq!(&big("5") + &big("-7"), big("-2"));
+        assert_eq!(&big("-5") + &big("7"), big("2"));
+        assert_eq!(&big("-5") + &big("5"), BigInt::zero());
+        assert_eq!(
+            &big("18446744073709551615") + &big("1"),
+            big("18446744073709551616")
+        );
+        assert_eq!(
+            &big("-18446744073709551616") + &big("1"),
+            big("-18446744073709551615")
+        );
+    }
+
+    #[test]
+    fn mul_works_beyond_64_bit() {
+        assert_eq!(
+            &big("9223372036854775807") * &big("9223372036854775807"),
+            big("85070591730234615847396907784232501249")
+        );
+        assert_eq!(&big("-3") * &big("4"), big("-12"));
+        assert_eq!(&big("-3") * &big("0"), BigInt::zero());
+    }
+
+    #[test]
+    fn ordering_respects_sign() {
+        assert!(big("-10") < big("-9"));
+        assert!(big("-1") < big("0"));
+        assert!(big("4294967296") > big("4294967295"));
+    }
+
+    #[test]
+    fn conversion_to_i128_checks_range() {
+        assert_eq!(BigInt::from_i128(i128::MIN).to_i128(), Some(i128::MIN));
+        assert_eq!(BigInt::from_i128(i128::MAX).to_i128(), Some(i128::MAX));
+        assert_eq!((&BigInt::from_i128(i128::MAX) + &big("1")).to_i128(), None);
+    }
+}
@@ -0,0 +1,16 @@
+use intcode::{assemble, to_intcode_string};
+use std::env;
+use std::fs::read_to_string;
+use std::path::Path;
+
+fn main() -> Result<(), String> {
+    let filename = env::args()
+        .nth(1)
+        .ok_or_else(|| "No file name given.".to_owned())?;
+    let content = read_to_string(Path::new(&filename)).map_err(|e| e.to_string())?;
+    let program = assemble(&content)?;
+
+    println!("{}", to_intcode_string(&program));
+
+    Ok(())
+}
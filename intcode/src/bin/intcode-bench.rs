@@ -0,0 +1,112 @@
+use intcode::{parse, run_program, run_program_fast, DenseMemory, RunResult, State};
+use std::env;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: intcode-bench [--runs <n>] [--input <v>,<v>,...] [file ...]
+Runs every program n times (default 100) with every engine and compares the results and times.
+Without files, a few built-in programs are used.";
+
+type Engine = fn(State, &[isize]) -> RunResult<DenseMemory>;
+
+const ENGINES: &[(&str, Engine)] = &[
+    ("run_program", run_program::<DenseMemory>),
+    ("fast", run_program_fast::<DenseMemory>),
+];
+
+// (name, program, input)
+fn builtin_programs() -> Vec<(String, Vec<isize>, Vec<isize>)> {
+    vec![
+        (
+            "day 9 quine".to_owned(),
+            vec![
+                109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+            ],
+            vec![],
+        ),
+        (
+            "day 5 compare with 8".to_owned(),
+            vec![
+                3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36,
+                98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000,
+                1, 20, 4, 20, 1105, 1, 46, 98, 99,
+            ],
+            vec![7],
+        ),
+        (
+            // sums up the numbers from 1 to the input in a tight loop
+            "sum loop".to_owned(),
+            vec![
+                3, 17, 1, 18, 17, 18, 1001, 17, -1, 17, 1005, 17, 2, 4, 18, 99, 0, 0, 0,
+            ],
+            vec![10_000],
+        ),
+    ]
+}
+
+fn main() -> Result<(), String> {
+    let mut runs: u32 = 100;
+    let mut input: Option<Vec<isize>> = None;
+    let mut programs: Vec<(String, Vec<isize>, Vec<isize>)> = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--runs" => {
+                let value = args.next().ok_or(USAGE)?;
+                runs = value.parse().map_err(|_| USAGE.to_owned())?;
+            }
+            "--input" => {
+                let value = args.next().ok_or(USAGE)?;
+                input = Some(parse(&value)?);
+            }
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            filename => {
+                let content = read_to_string(Path::new(filename)).map_err(|e| e.to_string())?;
+                programs.push((filename.to_owned(), parse(&content)?, vec![]));
+            }
+        }
+    }
+    if programs.is_empty() {
+        programs = builtin_programs();
+    }
+
+    for (name, program, default_input) in programs {
+        let input = input.clone().unwrap_or(default_input);
+        println!("{} ({} words, {} runs)", name, program.len(), runs);
+        let mut baseline: Option<(Duration, RunResult<DenseMemory>)> = None;
+        for (engine_name, engine) in ENGINES {
+            let start = Instant::now();
+            let mut result = engine(State::new(program.clone()), &input);
+            for _ in 1..runs {
+                result = engine(State::new(program.clone()), &input);
+            }
+            let elapsed = start.elapsed();
+            let per_run = elapsed / runs.max(1);
+            match &baseline {
+                None => {
+                    println!("  {:<12} {:>12.3?}/run", engine_name, per_run);
+                    baseline = Some((elapsed, result));
+                }
+                Some((baseline_elapsed, expected)) => {
+                    let speedup = baseline_elapsed.as_secs_f64() / elapsed.as_secs_f64();
+                    println!(
+                        "  {:<12} {:>12.3?}/run  {:.2}x",
+                        engine_name, per_run, speedup
+                    );
+                    if result != *expected {
+                        return Err(format!(
+                            "{}: the result of engine '{}' differs from '{}'",
+                            name, engine_name, ENGINES[0].0
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
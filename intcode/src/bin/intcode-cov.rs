@@ -0,0 +1,56 @@
+use intcode::{encode_ascii, parse, Coverage, State};
+use std::env;
+use std::fs::{read_to_string, write};
+use std::path::Path;
+
+const USAGE: &str = "usage: intcode-cov [--input <v>,<v>,...] [--ascii <file>] [--merge <export>]
+                   [--export <file>] <program>
+Runs the program once per --input (numbers) or --ascii (text file, e.g. a springscript) and
+prints the listing annotated with the coverage of all runs. --merge adds the coverage of an
+earlier export, --export writes the coverage in a format that can be compared with diff.";
+
+fn read(filename: &str) -> Result<String, String> {
+    read_to_string(Path::new(filename)).map_err(|e| format!("{}: {}", filename, e))
+}
+
+fn main() -> Result<(), String> {
+    let mut inputs: Vec<Vec<isize>> = Vec::new();
+    let mut merge: Vec<String> = Vec::new();
+    let mut export: Option<String> = None;
+    let mut filename: Option<String> = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => inputs.push(parse(&args.next().ok_or(USAGE)?)?),
+            "--ascii" => inputs.push(encode_ascii(&read(&args.next().ok_or(USAGE)?)?)?),
+            "--merge" => merge.push(args.next().ok_or(USAGE)?),
+            "--export" => export = Some(args.next().ok_or(USAGE)?),
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => filename = Some(arg),
+        }
+    }
+    let filename = filename.ok_or(USAGE)?;
+    let program = parse(&read(&filename)?)?;
+    if inputs.is_empty() && merge.is_empty() {
+        inputs.push(Vec::new());
+    }
+
+    let mut coverage = Coverage::new();
+    for export in &merge {
+        coverage.merge(&Coverage::from_text(&read(export)?)?);
+    }
+    for input in &inputs {
+        if let Err(error) = coverage.run_program(State::new(program.clone()), input) {
+            eprintln!("Run failed: {}", error);
+        }
+    }
+    print!("{}", coverage.annotate(&program));
+
+    if let Some(export) = export {
+        write(Path::new(&export), coverage.to_text()).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
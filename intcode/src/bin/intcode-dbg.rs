@@ -0,0 +1,296 @@
+use intcode::{disassemble, parse, Debugger, Journal, Memory, Snapshot, State, Stop};
+use std::env;
+use std::fs::{read, read_to_string, write};
+use std::io::{stdin, stdout, Write};
+use std::path::Path;
+
+const HELP: &str = "\
+commands (empty line repeats the last command):
+  break <addr>      b   set a breakpoint
+  watch <addr>      w   stop after writes to a memory cell
+  delete <addr>     d   remove the breakpoint and watchpoint at an address
+  step [n]          s   execute n instructions (default 1)
+  continue          c   run until a breakpoint, watchpoint, missing input or halt
+  record [n] [m]        record executed instructions for 'back' and 'rewind', with a full
+                        checkpoint every n instructions (default 10000), keeping m checkpoints
+                        (default 100)
+  back [n]              undo n instructions (default 1), 'set' and 'load' can not be undone
+  rewind                undo instructions up to and including the last input or output
+  input <v> ...     i   queue input values
+  ascii <text>      a   queue text as ASCII codes, followed by a newline
+  mem <addr> [n]    x   show n memory cells (default 8)
+  set <addr> <v>        write a memory cell
+  set ip <v>            move the instruction pointer
+  set rb <v>            change the relative base
+  list [addr] [n]   l   disassemble n instructions (default 10) at addr (default ip)
+  regs              r   show ip, relative base, queued input, breakpoints and watchpoints
+  output            o   show all output so far
+  save <file>           save the machine state (text format if the file name ends with .txt)
+  load <file>           load a machine state saved with 'save'
+  help              h   show this help
+  quit              q   exit the debugger";
+
+fn main() -> Result<(), String> {
+    let filename = env::args()
+        .nth(1)
+        .ok_or_else(|| "No file name given.".to_owned())?;
+    let content = read_to_string(Path::new(&filename)).map_err(|e| e.to_string())?;
+    let program = parse(&content)?;
+
+    let mut debugger = Debugger::new(State::new(program));
+    let mut last_command = String::new();
+    println!(
+        "Loaded {} words. Type 'help' for a list of commands.",
+        debugger.state.mem.len()
+    );
+    print_listing(&debugger, debugger.state.ip, 1);
+    loop {
+        print!("(dbg) ");
+        stdout().flush().map_err(|e| e.to_string())?;
+        let mut line = String::with_capacity(128);
+        if stdin().read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            line = last_command.clone();
+        } else {
+            last_command = line.clone();
+        }
+        match execute(&mut debugger, line.trim()) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => print_error(&e),
+        }
+    }
+
+    Ok(())
+}
+
+// returns false if the debugger should exit
+fn execute(debugger: &mut Debugger, line: &str) -> Result<bool, String> {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some(command) => command,
+        None => return Ok(true),
+    };
+    let args: Vec<&str> = words.collect();
+    match command {
+        "break" | "b" => {
+            debugger.breakpoints.insert(parse_arg(&args, 0, None)?);
+        }
+        "watch" | "w" => {
+            debugger.watchpoints.insert(parse_arg(&args, 0, None)?);
+        }
+        "delete" | "d" => {
+            let address = parse_arg(&args, 0, None)?;
+            let removed = debugger.breakpoints.remove(&address);
+            if !debugger.watchpoints.remove(&address) && !removed {
+                return Err(format!("no breakpoint or watchpoint at {}", address));
+            }
+        }
+        "step" | "s" => {
+            let count: usize = parse_arg(&args, 0, Some(1))?;
+            let output_len = debugger.output.len();
+            for _ in 0..count {
+                let (entry, stop) = debugger.step().map_err(|e| e.to_string())?;
+                if stop == Stop::Step || matches!(stop, Stop::Watchpoint { .. }) {
+                    println!("{}", entry);
+                }
+                if stop != Stop::Step {
+                    print_output(&debugger.output[output_len..]);
+                    print_stop(debugger, &stop);
+                    return Ok(true);
+                }
+            }
+            print_output(&debugger.output[output_len..]);
+            print_listing(debugger, debugger.state.ip, 1);
+        }
+        "continue" | "c" => {
+            let output_len = debugger.output.len();
+            let result = debugger.resume(None);
+            print_output(&debugger.output[output_len..]);
+            print_stop(debugger, &result.map_err(|e| e.to_string())?);
+        }
+        "record" => {
+            let interval = parse_arg(&args, 0, Some(10_000))?;
+            let max_checkpoints = parse_arg(&args, 1, Some(100))?;
+            debugger.journal = Some(Journal::new(interval, max_checkpoints));
+        }
+        "back" => {
+            let count = parse_arg(&args, 0, Some(1))?;
+            let undone = debugger.step_back(count);
+            print_undone(debugger, count, undone)?;
+        }
+        "rewind" => {
+            let undone = debugger.rewind_to_io();
+            print_undone(debugger, undone, undone)?;
+        }
+        "input" | "i" => {
+            for arg in &args {
+                let value = arg
+                    .parse::<isize>()
+                    .map_err(|e| format!("invalid input value '{}': {}", arg, e))?;
+                debugger.input.push_back(value);
+            }
+        }
+        "ascii" | "a" => {
+            let text = line
+                .split_once(char::is_whitespace)
+                .map(|(_, text)| text)
+                .unwrap_or("");
+            if !text.is_ascii() {
+                return Err("the text contains non-ASCII characters".to_owned());
+            }
+            debugger.input.extend(text.bytes().map(|b| b as isize));
+            debugger.input.push_back(10);
+        }
+        "mem" | "x" => {
+            let address: usize = parse_arg(&args, 0, None)?;
+            let count: usize = parse_arg(&args, 1, Some(8))?;
+            let values: Vec<String> = (address..address.saturating_add(count))
+                .map(|a| debugger.state.mem.read(a).to_string())
+                .collect();
+            println!("{:>6}: {}", address, values.join(", "));
+        }
+        "set" => {
+            // the edit can not be undone, so the recorded history ends here
+            let mut state = debugger.state.clone();
+            match args.first() {
+                Some(&"ip") => state.ip = parse_arg(&args, 1, None)?,
+                Some(&"rb") => state.rel_base = parse_arg(&args, 1, None)?,
+                _ => {
+                    let address: usize = parse_arg(&args, 0, None)?;
+                    let value: isize = parse_arg(&args, 1, None)?;
+                    state.mem.write(address, value).map_err(|e| e.to_string())?;
+                }
+            }
+            debugger.set_state(state);
+        }
+        "list" | "l" => {
+            let address = parse_arg(&args, 0, Some(debugger.state.ip))?;
+            let count = parse_arg(&args, 1, Some(10))?;
+            print_listing(debugger, address, count);
+        }
+        "regs" | "r" => {
+            let join = |values: Vec<String>| values.join(", ");
+            println!("ip: {}", debugger.state.ip);
+            println!("rb: {}", debugger.state.rel_base);
+            println!(
+                "input: [{}]",
+                join(debugger.input.iter().map(|v| v.to_string()).collect())
+            );
+            println!(
+                "breakpoints: [{}]",
+                join(debugger.breakpoints.iter().map(|v| v.to_string()).collect())
+            );
+            println!(
+                "watchpoints: [{}]",
+                join(debugger.watchpoints.iter().map(|v| v.to_string()).collect())
+            );
+        }
+        "output" | "o" => print_output(&debugger.output),
+        "save" => {
+            let filename = args.first().ok_or("missing file name")?;
+            let snapshot = Snapshot::new(debugger.state.clone(), None);
+            let content = if filename.ends_with(".txt") {
+                snapshot.to_text().into_bytes()
+            } else {
+                snapshot.to_bytes()
+            };
+            write(filename, content).map_err(|e| e.to_string())?;
+        }
+        "load" => {
+            let filename = args.first().ok_or("missing file name")?;
+            let content = read(filename).map_err(|e| e.to_string())?;
+            debugger.set_state(Snapshot::read(&content)?.state);
+            print_listing(debugger, debugger.state.ip, 1);
+        }
+        "help" | "h" => println!("{}", HELP),
+        "quit" | "q" => return Ok(false),
+        _ => return Err(format!("unknown command '{}', try 'help'", command)),
+    }
+    Ok(true)
+}
+
+fn print_undone(debugger: &Debugger, requested: u64, undone: u64) -> Result<(), String> {
+    if debugger.journal.is_none() {
+        return Err("not recording, use 'record' first".to_owned());
+    }
+    if undone < requested || undone == 0 {
+        println!("reached the start of the recording");
+    }
+    println!("undid {} instructions", undone);
+    print_listing(debugger, debugger.state.ip, 1);
+    Ok(())
+}
+
+// parse the nth argument, use `default` if it is missing
+fn parse_arg<T>(args: &[&str], n: usize, default: Option<T>) -> Result<T, String>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match (args.get(n), default) {
+        (Some(arg), _) => arg
+            .parse::<T>()
+            .map_err(|e| format!("invalid argument '{}': {}", arg, e)),
+        (None, Some(default)) => Ok(default),
+        (None, None) => Err(format!("missing argument {}, try 'help'", n + 1)),
+    }
+}
+
+fn print_stop(debugger: &Debugger, stop: &Stop) {
+    match stop {
+        Stop::Step | Stop::OutOfFuel => (),
+        Stop::Breakpoint(address) => println!("breakpoint at {}", address),
+        Stop::Watchpoint { address, old, new } => {
+            println!("watchpoint: [{}] changed from {} to {}", address, old, new)
+        }
+        Stop::InputRequired => println!("waiting for input, use 'input' or 'ascii'"),
+        Stop::Halt => println!("program halted"),
+    }
+    if *stop != Stop::Halt {
+        print_listing(debugger, debugger.state.ip, 1);
+    }
+}
+
+fn print_listing(debugger: &Debugger, address: usize, count: usize) {
+    let mem = debugger.state.mem.as_slice();
+    if address >= mem.len() {
+        println!("{:>6}: <outside of memory>", address);
+        return;
+    }
+    let end = mem
+        .len()
+        .min(address.saturating_add(count.saturating_mul(4)));
+    for mut line in disassemble(&mem[address..end]).into_iter().take(count) {
+        line.address += address;
+        let marker = if debugger.breakpoints.contains(&line.address) {
+            '*'
+        } else {
+            ' '
+        };
+        println!("{}{}", marker, line);
+    }
+}
+
+// printable ASCII output is printed as text, everything else as comma-separated numbers
+fn print_output(output: &[isize]) {
+    if output.is_empty() {
+        return;
+    }
+    if output.iter().all(|v| *v == 10 || (32..127).contains(v)) {
+        let text: String = output.iter().map(|v| *v as u8 as char).collect();
+        print!("{}", text);
+        if !text.ends_with('\n') {
+            println!();
+        }
+    } else {
+        let values: Vec<String> = output.iter().map(|v| v.to_string()).collect();
+        println!("output: {}", values.join(","));
+    }
+}
+
+fn print_error(error: &str) {
+    eprintln!("\x1b[1;31m{}\x1b[0m", error);
+}
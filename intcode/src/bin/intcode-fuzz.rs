@@ -0,0 +1,46 @@
+use intcode::{check_program, fuzz, parse, FuzzConfig};
+use std::env;
+use std::fs::read_to_string;
+use std::path::Path;
+
+const USAGE: &str = "usage: intcode-fuzz [--cases <n>] [--seed <s>] [--fuel <n>]
+       intcode-fuzz [--input <v>,<v>,...] [--fuel <n>] <program>
+Runs n random programs (default 1000) on all engines and checks that none panics, huge writes
+fail instead of allocating, all engines agree and resuming after every input gives the same
+result as a single run. A failing case is shrunk and printed. With a program, only that
+program and input are checked, e.g. to reproduce a failure.";
+
+fn number(value: Option<String>) -> Result<u64, String> {
+    value.ok_or(USAGE)?.parse().map_err(|_| USAGE.to_owned())
+}
+
+fn main() -> Result<(), String> {
+    let mut config = FuzzConfig::default();
+    let mut input: Vec<isize> = Vec::new();
+    let mut filename: Option<String> = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cases" => config.cases = number(args.next())?,
+            "--seed" => config.seed = number(args.next())?,
+            "--fuel" => config.max_instructions = number(args.next())?,
+            "--input" => input = parse(&args.next().ok_or(USAGE)?)?,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => filename = Some(arg),
+        }
+    }
+
+    if let Some(filename) = filename {
+        let content = read_to_string(Path::new(&filename)).map_err(|e| e.to_string())?;
+        let program = parse(&content)?;
+        check_program(&program, &input, config.max_instructions)?;
+        println!("All invariants hold");
+    } else {
+        fuzz(&config)?;
+        println!("{} cases passed", config.cases);
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use intcode::{analyze, parse, Severity};
+use std::env;
+use std::fs::read_to_string;
+use std::path::Path;
+
+// Usage: intcode-lint [--cfg] <file>
+// Prints the issues found by the static analysis and fails if there are errors. With `--cfg`,
+// the basic blocks of the control-flow graph and the data ranges are printed as well.
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let print_cfg = args.iter().any(|arg| arg == "--cfg");
+    let filename = args
+        .iter()
+        .find(|arg| !arg.starts_with("--"))
+        .ok_or_else(|| "No file name given.".to_owned())?;
+    let content = read_to_string(Path::new(filename)).map_err(|e| e.to_string())?;
+    let program = parse(&content)?;
+
+    let analysis = analyze(&program);
+    for issue in &analysis.issues {
+        println!("{}:{}", filename, issue);
+    }
+    let data_ranges = analysis.data_ranges();
+    if print_cfg {
+        for block in analysis.blocks.values() {
+            let successors: Vec<String> = block.successors.iter().map(|s| s.to_string()).collect();
+            println!(
+                "block {}..{} -> [{}]{}",
+                block.start,
+                block.end,
+                successors.join(", "),
+                if block.indirect { " + indirect" } else { "" }
+            );
+        }
+        for (start, end) in &data_ranges {
+            println!("data {}..{}", start, end);
+        }
+    }
+    println!(
+        "{} words: {} code, {} data in {} ranges, {} blocks",
+        analysis.len,
+        analysis.code_size(),
+        analysis.len - analysis.code_size(),
+        data_ranges.len(),
+        analysis.blocks.len()
+    );
+    let errors = analysis.count(Severity::Error);
+    println!(
+        "{} errors, {} warnings, {} notes",
+        errors,
+        analysis.count(Severity::Warning),
+        analysis.count(Severity::Note)
+    );
+
+    if errors > 0 {
+        return Err(format!("{} errors found", errors));
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use intcode::{parse, run_traced, Limits, Profiler, State};
+use std::env;
+use std::fs::{read_to_string, write};
+use std::path::Path;
+
+const USAGE: &str =
+    "usage: intcode-prof [--input <v>,<v>,...] [--top <n>] [--folded <file>] <program>
+Runs the program with the given input and prints the opcodes, the n hottest addresses and
+loops (default 20) and the conditional jumps. With --folded, the guessed call stacks are
+written in the folded format of flame graph tools.";
+
+fn main() -> Result<(), String> {
+    let mut input: Vec<isize> = Vec::new();
+    let mut top: usize = 20;
+    let mut folded: Option<String> = None;
+    let mut filename: Option<String> = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input = parse(&args.next().ok_or(USAGE)?)?,
+            "--top" => {
+                let value = args.next().ok_or(USAGE)?;
+                top = value.parse().map_err(|_| USAGE.to_owned())?;
+            }
+            "--folded" => folded = Some(args.next().ok_or(USAGE)?),
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => filename = Some(arg),
+        }
+    }
+    let filename = filename.ok_or(USAGE)?;
+    let content = read_to_string(Path::new(&filename)).map_err(|e| e.to_string())?;
+    let program = parse(&content)?;
+
+    let mut state = State::new(program);
+    let mut profiler = Profiler::new();
+    let mut output: Vec<isize> = Vec::new();
+    let result = run_traced(
+        &mut state,
+        &mut &input[..],
+        &mut output,
+        &Limits::unlimited(),
+        &mut profiler,
+    );
+    match &result {
+        Ok(status) => println!("{:?} with {} output values", status, output.len()),
+        Err(error) => println!("Failed: {}", error),
+    }
+    println!();
+    print!("{}", profiler.report(top));
+
+    if let Some(folded) = folded {
+        write(Path::new(&folded), profiler.folded_stacks()).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
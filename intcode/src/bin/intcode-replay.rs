@@ -0,0 +1,73 @@
+use intcode::{parse, replay, Recorder, State, Transcript};
+use std::env;
+use std::fs::{read_to_string, write};
+use std::path::Path;
+
+const USAGE: &str = "usage: intcode-replay <program> <transcript> ...
+       intcode-replay --record <transcript> [--input <v>,<v>,...] <program>
+Replays the transcripts and fails on the first divergence, or runs the program with the given
+input and records a transcript.";
+
+fn main() -> Result<(), String> {
+    let mut record: Option<String> = None;
+    let mut input: Vec<isize> = Vec::new();
+    let mut files: Vec<String> = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => record = Some(args.next().ok_or(USAGE)?),
+            "--input" => input = parse(&args.next().ok_or(USAGE)?)?,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => files.push(arg),
+        }
+    }
+    let (program_file, transcript_files) = files.split_first().ok_or(USAGE)?;
+    let content = read_to_string(Path::new(program_file)).map_err(|e| e.to_string())?;
+    let program = parse(&content)?;
+
+    if let Some(transcript_file) = record {
+        let mut recorder = Recorder::new(State::new(program));
+        let status = recorder.run(&mut &input[..], &mut Vec::new())?;
+        let (_, transcript) = recorder.into_parts();
+        write(Path::new(&transcript_file), transcript.to_text()).map_err(|e| e.to_string())?;
+        println!(
+            "{}: {} events, stopped with {:?}",
+            transcript_file,
+            transcript.events.len(),
+            status
+        );
+        return Ok(());
+    }
+
+    if transcript_files.is_empty() {
+        return Err(USAGE.to_owned());
+    }
+    let mut diverged = 0;
+    for transcript_file in transcript_files {
+        let text = read_to_string(Path::new(transcript_file)).map_err(|e| e.to_string())?;
+        let transcript: Transcript = Transcript::from_text(&text)?;
+        match replay(&mut State::new(program.clone()), &transcript) {
+            Ok(()) => println!(
+                "{}: ok ({} events, {} resume points)",
+                transcript_file,
+                transcript.events.len(),
+                transcript.resume_points().len()
+            ),
+            Err(divergence) => {
+                diverged += 1;
+                println!("{}: {}", transcript_file, divergence);
+            }
+        }
+    }
+    if diverged > 0 {
+        return Err(format!(
+            "{} of {} replays diverged",
+            diverged,
+            transcript_files.len()
+        ));
+    }
+    Ok(())
+}
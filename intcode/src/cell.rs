@@ -0,0 +1,136 @@
+use crate::BigInt;
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::num::Wrapping;
+
+// The type of a single memory cell. The cell type also decides what happens when an addition or
+// multiplication overflows:
+// - `isize`, `i64`, `i128`: the operation fails with `IntcodeError::Overflow` (the same in debug
+//   and release builds)
+// - `Wrapping<isize>`, `Wrapping<i64>`, `Wrapping<i128>`: the result wraps around
+// - `BigInt`: never overflows
+// `isize` is the default for compatibility, but its range depends on the host. Use `i64` or
+// larger if a program needs a fixed range.
+pub trait Cell: Clone + Eq + Ord + Hash + Debug + Display {
+    fn zero() -> Self;
+
+    fn one() -> Self;
+
+    fn from_isize(value: isize) -> Self;
+
+    // `None` if the value does not fit into an `isize`
+    fn to_isize(&self) -> Option<isize>;
+
+    // parse a single decimal number
+    fn from_token(token: &str) -> Result<Self, String>;
+
+    // `None` if the result overflows
+    fn try_add(&self, other: &Self) -> Option<Self>;
+
+    // `None` if the result overflows
+    fn try_mul(&self, other: &Self) -> Option<Self>;
+}
+
+// checked arithmetic for the primitive integer types
+macro_rules! impl_checked_cell {
+    ($($t:ty),*) => {$(
+        impl Cell for $t {
+            fn zero() -> $t {
+                0
+            }
+
+            fn one() -> $t {
+                1
+            }
+
+            fn from_isize(value: isize) -> $t {
+                value as $t
+            }
+
+            fn to_isize(&self) -> Option<isize> {
+                isize::try_from(*self).ok()
+            }
+
+            fn from_token(token: &str) -> Result<$t, String> {
+                token.parse::<$t>().map_err(|e| e.to_string())
+            }
+
+            fn try_add(&self, other: &$t) -> Option<$t> {
+                self.checked_add(*other)
+            }
+
+            fn try_mul(&self, other: &$t) -> Option<$t> {
+                self.checked_mul(*other)
+            }
+        }
+    )*};
+}
+
+// wrapping arithmetic for the primitive integer types
+macro_rules! impl_wrapping_cell {
+    ($($t:ty),*) => {$(
+        impl Cell for Wrapping<$t> {
+            fn zero() -> Wrapping<$t> {
+                Wrapping(0)
+            }
+
+            fn one() -> Wrapping<$t> {
+                Wrapping(1)
+            }
+
+            fn from_isize(value: isize) -> Wrapping<$t> {
+                Wrapping(value as $t)
+            }
+
+            fn to_isize(&self) -> Option<isize> {
+                isize::try_from(self.0).ok()
+            }
+
+            fn from_token(token: &str) -> Result<Wrapping<$t>, String> {
+                token.parse::<$t>().map(Wrapping).map_err(|e| e.to_string())
+            }
+
+            fn try_add(&self, other: &Wrapping<$t>) -> Option<Wrapping<$t>> {
+                Some(*self + *other)
+            }
+
+            fn try_mul(&self, other: &Wrapping<$t>) -> Option<Wrapping<$t>> {
+                Some(*self * *other)
+            }
+        }
+    )*};
+}
+
+impl_checked_cell!(isize, i64, i128);
+impl_wrapping_cell!(isize, i64, i128);
+
+impl Cell for BigInt {
+    fn zero() -> BigInt {
+        BigInt::zero()
+    }
+
+    fn one() -> BigInt {
+        BigInt::from_i128(1)
+    }
+
+    fn from_isize(value: isize) -> BigInt {
+        BigInt::from(value)
+    }
+
+    fn to_isize(&self) -> Option<isize> {
+        self.to_i128().and_then(|value| isize::try_from(value).ok())
+    }
+
+    fn from_token(token: &str) -> Result<BigInt, String> {
+        token.parse()
+    }
+
+    fn try_add(&self, other: &BigInt) -> Option<BigInt> {
+        Some(self + other)
+    }
+
+    fn try_mul(&self, other: &BigInt) -> Option<BigInt> {
+        Some(self * other)
+    }
+}
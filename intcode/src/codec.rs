@@ -0,0 +1,259 @@
+// Helpers for the compact binary formats (trace logs, snapshots) and the line based text
+// formats (snapshots, transcripts, coverage exports).
+//
+// Unsigned numbers are stored as LEB128 varints, signed numbers are zigzag encoded first.
+// Cells are stored as `zigzag(value) + 1` if they fit into an `isize`. Larger cells are stored as
+// a 0 followed by the length and the bytes of their decimal representation.
+use crate::Cell;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct DecodeError {
+    // byte offset in the input at which decoding failed
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "invalid data at byte {}: {}", self.offset, self.message)
+    }
+}
+
+impl Error for DecodeError {}
+
+impl From<DecodeError> for String {
+    fn from(error: DecodeError) -> String {
+        error.to_string()
+    }
+}
+
+pub(crate) fn write_unsigned(out: &mut Vec<u8>, mut value: u128) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn write_signed(out: &mut Vec<u8>, value: isize) {
+    write_unsigned(out, zigzag(value as i128));
+}
+
+pub(crate) fn write_cell<C: Cell>(out: &mut Vec<u8>, value: &C) {
+    match value.to_isize() {
+        Some(value) => write_unsigned(out, zigzag(value as i128) + 1),
+        None => {
+            let digits = value.to_string();
+            write_unsigned(out, 0);
+            write_unsigned(out, digits.len() as u128);
+            out.extend_from_slice(digits.as_bytes());
+        }
+    }
+}
+
+// Write the first lines of a text format: its header comment and `version: <n>`
+pub(crate) fn write_text_header(text: &mut String, header: &str, version: u8) {
+    text.push_str(header);
+    text.push_str("\nversion: ");
+    text.push_str(&version.to_string());
+    text.push('\n');
+}
+
+// The content lines of a text format written with `write_text_header` and their line numbers,
+// after checking the header and the version. `#` starts a comment, empty lines are left out.
+pub(crate) fn text_lines<'a>(
+    text: &'a str,
+    header: &str,
+    version: u8,
+) -> Result<impl Iterator<Item = (usize, &'a str)> + 'a, DecodeError> {
+    if !text.starts_with(header) {
+        let format = header.trim_start_matches('#').trim();
+        return Err(line_error(text, 1, format!("not an {}", format)));
+    }
+    let mut lines = text.lines().enumerate().filter_map(|(i, line)| {
+        let content = line.split('#').next().unwrap_or("").trim();
+        if content.is_empty() {
+            None
+        } else {
+            Some((i + 1, content))
+        }
+    });
+    match lines.next() {
+        Some((line_nr, line)) => match line.split_once(':') {
+            Some((key, value)) if key.trim() == "version" => {
+                if value.trim() != version.to_string() {
+                    let message = format!("unsupported version {}", value.trim());
+                    return Err(line_error(text, line_nr, message));
+                }
+            }
+            _ => {
+                let message = "expected 'version: ...'".to_owned();
+                return Err(line_error(text, line_nr, message));
+            }
+        },
+        None => {
+            let message = "missing 'version'".to_owned();
+            return Err(line_error(text, text.lines().count(), message));
+        }
+    }
+    Ok(lines)
+}
+
+// An error in line `line_nr` (starting at 1) of a text format, at the offset of the line
+pub(crate) fn line_error(text: &str, line_nr: usize, message: String) -> DecodeError {
+    DecodeError {
+        offset: text
+            .lines()
+            .take(line_nr.saturating_sub(1))
+            .map(|line| line.len() + 1)
+            .sum(),
+        message: format!("line {}: {}", line_nr, message),
+    }
+}
+
+// FNV-1a hash, used as checksum to detect damaged files
+pub(crate) fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn zigzag(value: i128) -> u128 {
+    ((value << 1) ^ (value >> 127)) as u128
+}
+
+fn unzigzag(value: u128) -> i128 {
+    (value >> 1) as i128 ^ -((value & 1) as i128)
+}
+
+// Reads values from a byte slice, keeping track of the position for error messages
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, offset: 0 }
+    }
+
+    pub(crate) fn is_at_end(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+
+    pub(crate) fn error(&self, message: String) -> DecodeError {
+        DecodeError {
+            offset: self.offset,
+            message,
+        }
+    }
+
+    pub(crate) fn bytes(&mut self, count: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() - self.offset < count {
+            return Err(self.error("unexpected end of data".to_owned()));
+        }
+        let result = &self.bytes[self.offset..self.offset + count];
+        self.offset += count;
+        Ok(result)
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn unsigned(&mut self) -> Result<u128, DecodeError> {
+        let mut result: u128 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 128 || (shift == 126 && byte & 0x7f > 0x03) {
+                return Err(self.error("varint is too long".to_owned()));
+            }
+            result |= ((byte & 0x7f) as u128) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    pub(crate) fn usize(&mut self) -> Result<usize, DecodeError> {
+        let value = self.unsigned()?;
+        if value > usize::MAX as u128 {
+            return Err(self.error(format!("{} does not fit into usize", value)));
+        }
+        Ok(value as usize)
+    }
+
+    pub(crate) fn signed(&mut self) -> Result<isize, DecodeError> {
+        let value = unzigzag(self.unsigned()?);
+        if value < isize::MIN as i128 || value > isize::MAX as i128 {
+            return Err(self.error(format!("{} does not fit into isize", value)));
+        }
+        Ok(value as isize)
+    }
+
+    pub(crate) fn cell<C: Cell>(&mut self) -> Result<C, DecodeError> {
+        match self.unsigned()? {
+            0 => {
+                let len = self.usize()?;
+                let digits = self.bytes(len)?;
+                let digits = std::str::from_utf8(digits)
+                    .map_err(|_| self.error("cell is not valid UTF-8".to_owned()))?;
+                C::from_token(digits).map_err(|message| self.error(message))
+            }
+            value => {
+                let value = unzigzag(value - 1);
+                if value < isize::MIN as i128 || value > isize::MAX as i128 {
+                    return Err(self.error(format!("{} does not fit into isize", value)));
+                }
+                Ok(C::from_isize(value as isize))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BigInt;
+
+    #[test]
+    fn numbers_and_cells_round_trip() {
+        // given
+        let big: BigInt = "-123456789012345678901234567890"
+            .parse()
+            .expect("valid number");
+        let mut out = Vec::new();
+
+        // when
+        write_unsigned(&mut out, 300);
+        write_signed(&mut out, isize::MIN);
+        write_signed(&mut out, -1);
+        write_cell(&mut out, &isize::MAX);
+        write_cell(&mut out, &big);
+        let mut reader = Reader::new(&out);
+
+        // then
+        assert_eq!(out[..2], [0xac, 0x02]);
+        assert_eq!(reader.unsigned(), Ok(300));
+        assert_eq!(reader.signed(), Ok(isize::MIN));
+        assert_eq!(reader.signed(), Ok(-1));
+        assert_eq!(reader.cell::<isize>(), Ok(isize::MAX));
+        assert_eq!(reader.cell::<BigInt>(), Ok(big));
+        assert!(reader.is_at_end());
+        assert_eq!(
+            reader.byte(),
+            Err(DecodeError {
+                offset: out.len(),
+                message: "unexpected end of data".to_owned()
+            })
+        );
+    }
+}
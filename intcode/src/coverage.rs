@@ -0,0 +1,319 @@
+// Code coverage of a program, accumulated over many runs.
+//
+// `Coverage` counts how often each address was executed and, for the conditional jumps, in
+// which directions they went. `annotate` renders a listing of the program with the coverage of
+// every line; `to_text` exports the coverage in a line based format that is stable for the same
+// coverage, so exports for different inputs can be compared with `diff`:
+//
+//     # intcode coverage
+//     version: 1
+//     runs: 2
+//     0: 2
+//     10: 300 taken=298 not_taken=2
+//
+// Every line lists an executed address and its execution count, conditional jumps also list
+// how often they were taken.
+use crate::codec::{line_error, text_lines, write_text_header, DecodeError};
+use crate::disasm::Line;
+use crate::profile::jump_taken;
+use crate::{
+    run_traced, BranchCount, Cell, Instruction, Limits, Memory, RunError, RunResult, State,
+    TraceEntry, TraceSink,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const TEXT_HEADER: &str = "# intcode coverage";
+const VERSION: u8 = 1;
+
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub struct Coverage {
+    pub runs: u64,
+    // execution count by address
+    executed: BTreeMap<usize, u64>,
+    branches: BTreeMap<usize, BranchCount>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    // Like `run_program`, but the executed instructions are added to the coverage
+    pub fn run_program<M: Memory>(
+        &mut self,
+        mut state: State<M>,
+        input: &[M::Cell],
+    ) -> RunResult<M> {
+        self.runs += 1;
+        let mut output: Vec<M::Cell> = Vec::new();
+        match run_traced(
+            &mut state,
+            &mut &input[..],
+            &mut output,
+            &Limits::unlimited(),
+            self,
+        ) {
+            Ok(status) => Ok((state, status, output)),
+            Err(error) => Err(RunError {
+                error,
+                state,
+                output,
+            }),
+        }
+    }
+
+    // add the coverage of other runs
+    pub fn merge(&mut self, other: &Coverage) {
+        self.runs += other.runs;
+        for (address, count) in &other.executed {
+            *self.executed.entry(*address).or_insert(0) += count;
+        }
+        for (address, branch) in &other.branches {
+            let own = self.branches.entry(*address).or_default();
+            own.taken += branch.taken;
+            own.not_taken += branch.not_taken;
+        }
+    }
+
+    pub fn count(&self, address: usize) -> u64 {
+        self.executed.get(&address).copied().unwrap_or(0)
+    }
+
+    pub fn is_covered(&self, address: usize) -> bool {
+        self.executed.contains_key(&address)
+    }
+
+    // the executed addresses in ascending order
+    pub fn covered(&self) -> impl Iterator<Item = usize> + '_ {
+        self.executed.keys().copied()
+    }
+
+    pub fn branch(&self, address: usize) -> Option<BranchCount> {
+        self.branches.get(&address).copied()
+    }
+
+    // The program split into instructions and data like `disassemble`, but aligned to the
+    // executed addresses: an instruction that was not executed and overlaps an executed one is
+    // rendered as data.
+    pub fn lines(&self, program: &[isize]) -> Vec<Line> {
+        let mut lines: Vec<Line> = Vec::with_capacity(program.len());
+        let mut address: usize = 0;
+        while address < program.len() {
+            let mut instruction = Instruction::decode(address, program[address])
+                .ok()
+                .filter(|instruction| address + instruction.size() <= program.len());
+            let mut size = instruction.map(|i| i.size()).unwrap_or(1);
+            if !self.is_covered(address)
+                && (address + 1..address + size).any(|a| self.is_covered(a))
+            {
+                instruction = None;
+                size = 1;
+            }
+            lines.push(Line {
+                address,
+                words: program[address..address + size].to_vec(),
+                instruction,
+            });
+            address += size;
+        }
+        lines
+    }
+
+    // (covered, total) number of instructions in the listing of `program`
+    pub fn summary(&self, program: &[isize]) -> (usize, usize) {
+        let lines = self.lines(program);
+        let instructions = lines.iter().filter(|line| line.instruction.is_some());
+        let covered = instructions
+            .clone()
+            .filter(|line| self.is_covered(line.address))
+            .count();
+        (covered, instructions.count())
+    }
+
+    // A listing of `program` with a marker and the execution count in front of every line:
+    // '+' executed, '-' never executed, '~' a conditional jump that only went one way. Data
+    // lines that were never executed have no marker.
+    pub fn annotate(&self, program: &[isize]) -> String {
+        let mut text = String::with_capacity(program.len() * 48);
+        for line in self.lines(program) {
+            let count = self.count(line.address);
+            let branch = self.branch(line.address);
+            let marker = match branch {
+                _ if count == 0 && line.instruction.is_none() => ' ',
+                _ if count == 0 => '-',
+                Some(branch) if branch.taken == 0 || branch.not_taken == 0 => '~',
+                _ => '+',
+            };
+            // writing to a string can not fail
+            let _ = write!(text, "{} {:>10} {}", marker, count, line);
+            if let Some(branch) = branch {
+                let _ = write!(
+                    text,
+                    " (taken {}, not taken {})",
+                    branch.taken, branch.not_taken
+                );
+            }
+            text.push('\n');
+        }
+        let (covered, total) = self.summary(program);
+        let _ = writeln!(
+            text,
+            "{} of {} instructions covered ({:.1}%) in {} runs",
+            covered,
+            total,
+            100.0 * covered as f64 / total.max(1) as f64,
+            self.runs
+        );
+        text
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::with_capacity(self.executed.len() * 12 + 64);
+        write_text_header(&mut text, TEXT_HEADER, VERSION);
+        let _ = writeln!(text, "runs: {}", self.runs);
+        for (address, count) in &self.executed {
+            let _ = write!(text, "{}: {}", address, count);
+            if let Some(branch) = self.branches.get(address) {
+                let _ = write!(
+                    text,
+                    " taken={} not_taken={}",
+                    branch.taken, branch.not_taken
+                );
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Coverage, DecodeError> {
+        let mut coverage = Coverage::new();
+        for (line_nr, line) in text_lines(text, TEXT_HEADER, VERSION)? {
+            let error = |message: String| line_error(text, line_nr, message);
+            let number = |value: &str| -> Result<u64, DecodeError> {
+                value
+                    .parse::<u64>()
+                    .map_err(|e| error(format!("invalid number '{}': {}", value, e)))
+            };
+            let (key, values) = line
+                .split_once(':')
+                .ok_or_else(|| error("expected '<address>: <count>'".to_owned()))?;
+            let mut values = values.split_whitespace();
+            let value = values
+                .next()
+                .ok_or_else(|| error("missing value".to_owned()))?;
+            match key.trim() {
+                "runs" => coverage.runs = number(value)?,
+                address => {
+                    let address = number(address)? as usize;
+                    coverage.executed.insert(address, number(value)?);
+                    let mut branch: Option<BranchCount> = None;
+                    for field in values {
+                        let unknown = || error(format!("unknown field '{}'", field));
+                        let (name, count) = field.split_once('=').ok_or_else(unknown)?;
+                        let count = number(count)?;
+                        let branch = branch.get_or_insert_with(BranchCount::default);
+                        match name {
+                            "taken" => branch.taken = count,
+                            "not_taken" => branch.not_taken = count,
+                            _ => return Err(unknown()),
+                        }
+                    }
+                    if let Some(branch) = branch {
+                        coverage.branches.insert(address, branch);
+                    }
+                }
+            }
+        }
+        Ok(coverage)
+    }
+}
+
+impl<C: Cell> TraceSink<C> for Coverage {
+    fn record(&mut self, entry: &TraceEntry<C>) {
+        let address = entry.step.address;
+        *self.executed.entry(address).or_insert(0) += 1;
+        match jump_taken(entry) {
+            Some(true) => self.branches.entry(address).or_default().taken += 1,
+            Some(false) => self.branches.entry(address).or_default().not_taken += 1,
+            None => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{assemble, ReturnStatus};
+
+    // outputs 1 for positive input and 0 otherwise
+    const SIGN: &str = "
+                IN [x]
+                LT #0, [x], [x]
+                JZ [x], #zero
+                OUT #1
+                HLT
+        zero:   OUT #0
+                HLT
+        x:      DATA 0
+    ";
+
+    #[test]
+    fn coverage_accumulates_runs() {
+        // given
+        let program = assemble(SIGN).expect("Expected valid program");
+        let mut coverage = Coverage::new();
+
+        // when
+        let result1 = coverage.run_program(State::new(program.clone()), &[5]);
+        let summary1 = coverage.summary(&program);
+        let annotated1 = coverage.annotate(&program);
+        let result2 = coverage.run_program(State::new(program.clone()), &[-5]);
+
+        // then
+        assert_eq!(result1.expect("Expected valid run").1, ReturnStatus::Halt);
+        assert_eq!(result2.expect("Expected valid run").2, vec![0]);
+        assert_eq!(summary1, (5, 7));
+        assert_eq!(coverage.summary(&program), (7, 7));
+        assert_eq!(
+            coverage.branch(6),
+            Some(BranchCount {
+                taken: 1,
+                not_taken: 1
+            })
+        );
+        let markers: Vec<char> = annotated1
+            .lines()
+            .filter_map(|line| line.chars().next())
+            .collect();
+        assert_eq!(markers, vec!['+', '+', '~', '+', '+', '-', '-', ' ', '5']);
+        assert!(annotated1.ends_with("5 of 7 instructions covered (71.4%) in 1 runs\n"));
+    }
+
+    #[test]
+    fn coverage_export_round_trips_and_merges() {
+        // given
+        let program = assemble(SIGN).expect("Expected valid program");
+        let mut positive = Coverage::new();
+        let mut negative = Coverage::new();
+        let _ = positive.run_program(State::new(program.clone()), &[5]);
+        let _ = negative.run_program(State::new(program.clone()), &[-5]);
+
+        // when
+        let text = positive.to_text();
+        let parsed = Coverage::from_text(&text);
+        let mut merged = positive.clone();
+        merged.merge(&negative);
+
+        // then
+        assert_eq!(
+            text,
+            "# intcode coverage\nversion: 1\nruns: 1\n0: 1\n2: 1\n6: 1 taken=0 not_taken=1\n\
+             9: 1\n11: 1\n"
+        );
+        assert_eq!(parsed, Ok(positive));
+        assert_eq!(merged.runs, 2);
+        assert_eq!(merged.count(6), 2);
+        assert!(Coverage::from_text("# intcode coverage\nversion: 1\n3: x\n").is_err());
+    }
+}
@@ -0,0 +1,284 @@
+use crate::{DenseMemory, IntcodeError, Journal, Memory, State, StepEvent, TraceEntry};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+// Why `Debugger::step` or `Debugger::resume` returned
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Stop<C = isize> {
+    // a single instruction was executed
+    Step,
+    // the instruction pointer reached the breakpoint at the given address
+    Breakpoint(usize),
+    // the watched cell at `address` was written
+    Watchpoint { address: usize, old: C, new: C },
+    // the program needs input, but the input queue is empty
+    InputRequired,
+    Halt,
+    // the instruction limit of `resume` was reached
+    OutOfFuel,
+}
+
+// the executed instruction and why the debugger stopped
+type StepResult<C> = Result<(TraceEntry<C>, Stop<C>), IntcodeError>;
+
+// A machine with breakpoints, watchpoints and an input queue, for interactive debugging.
+// All fields can be modified between steps.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Debugger<M: Memory = DenseMemory> {
+    pub state: State<M>,
+    pub breakpoints: BTreeSet<usize>,
+    // addresses of memory cells; the debugger stops after each write to one of them
+    pub watchpoints: BTreeSet<usize>,
+    // values for the next input instructions
+    pub input: VecDeque<M::Cell>,
+    // everything the program wrote so far
+    pub output: Vec<M::Cell>,
+    // records the executed instructions for `step_back` and `rewind_to_io` if set
+    pub journal: Option<Journal<M>>,
+}
+
+impl<M: Memory + Clone> Debugger<M> {
+    pub fn new(state: State<M>) -> Debugger<M> {
+        Debugger {
+            state,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            input: VecDeque::new(),
+            output: Vec::new(),
+            journal: None,
+        }
+    }
+
+    // Replace the state, e.g. with a loaded or edited copy. The journal is cleared, as its
+    // history belongs to the old state and can not be restored on top of the new one.
+    pub fn set_state(&mut self, state: State<M>) {
+        self.state = state;
+        if let Some(journal) = &mut self.journal {
+            journal.clear();
+        }
+    }
+
+    // Execute a single instruction, breakpoints are ignored. The returned entry describes the
+    // instruction; if the program blocks on input or halts, nothing was executed.
+    pub fn step(&mut self) -> StepResult<M::Cell> {
+        let watched: BTreeMap<usize, M::Cell> = self
+            .watchpoints
+            .iter()
+            .map(|address| (*address, self.state.mem.read(*address)))
+            .collect();
+        let entry = match &mut self.journal {
+            Some(journal) => journal.step(&mut self.state, &mut self.input)?,
+            None => self.state.step_traced(&mut self.input)?,
+        };
+        let stop = match &entry.step.event {
+            StepEvent::InputRequired => Stop::InputRequired,
+            StepEvent::Halt => Stop::Halt,
+            event => {
+                if let StepEvent::Output(value) = event {
+                    self.output.push(value.clone());
+                }
+                match &entry.step.write {
+                    Some((address, new)) if watched.contains_key(address) => Stop::Watchpoint {
+                        address: *address,
+                        old: watched[address].clone(),
+                        new: new.clone(),
+                    },
+                    _ => Stop::Step,
+                }
+            }
+        };
+        Ok((entry, stop))
+    }
+
+    // Execute instructions until a breakpoint or watchpoint is hit, the program blocks on input
+    // or halts. A breakpoint at the current instruction does not stop the debugger, so calling
+    // `resume` again after a breakpoint continues the program.
+    pub fn resume(&mut self, max_instructions: Option<u64>) -> Result<Stop<M::Cell>, IntcodeError> {
+        let mut executed: u64 = 0;
+        loop {
+            if max_instructions.map(|max| executed >= max) == Some(true) {
+                return Ok(Stop::OutOfFuel);
+            }
+            match self.step()?.1 {
+                Stop::Step if self.breakpoints.contains(&self.state.ip) => {
+                    return Ok(Stop::Breakpoint(self.state.ip))
+                }
+                Stop::Step => (),
+                stop => return Ok(stop),
+            }
+            executed += 1;
+        }
+    }
+
+    // Undo up to `count` instructions, the input they consumed is put back into the input
+    // queue and their output is removed. Returns the number of undone instructions, which is
+    // less than `count` if the journal does not reach back far enough (or is not enabled).
+    pub fn step_back(&mut self, count: u64) -> u64 {
+        let (events, undone) = match &mut self.journal {
+            Some(journal) => {
+                let start = journal.position();
+                let events = journal.rewind_to(&mut self.state, start.saturating_sub(count));
+                (events, start - journal.position())
+            }
+            None => return 0,
+        };
+        for event in events {
+            self.undo_io(event);
+        }
+        undone
+    }
+
+    // Undo instructions up to and including the last input or output instruction. Returns the
+    // number of undone instructions.
+    pub fn rewind_to_io(&mut self) -> u64 {
+        let (count, event) = match &mut self.journal {
+            Some(journal) => journal.rewind_to_io(&mut self.state),
+            None => return 0,
+        };
+        if let Some(event) = event {
+            self.undo_io(event);
+        }
+        count
+    }
+
+    fn undo_io(&mut self, event: StepEvent<M::Cell>) {
+        match event {
+            StepEvent::Input(value) => self.input.push_front(value),
+            StepEvent::Output(_) => {
+                self.output.pop();
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_programs::COUNTDOWN;
+
+    #[test]
+    fn resume_stops_at_breakpoints_and_input() {
+        // given
+        let mut debugger = Debugger::new(State::new(COUNTDOWN.to_vec()));
+        debugger.breakpoints.insert(4);
+
+        // when
+        let stop1 = debugger.resume(None).expect("Expected valid run");
+        debugger.input.push_back(2);
+        let stop2 = debugger.resume(None).expect("Expected valid run");
+        let stop3 = debugger.resume(None).expect("Expected valid run");
+        debugger.breakpoints.clear();
+        let stop4 = debugger.resume(None).expect("Expected valid run");
+
+        // then
+        assert_eq!(stop1, Stop::InputRequired);
+        assert_eq!(stop2, Stop::Breakpoint(4));
+        assert_eq!(stop3, Stop::Breakpoint(4));
+        assert_eq!(stop4, Stop::Halt);
+        assert_eq!(debugger.output, vec![2, 1]);
+    }
+
+    #[test]
+    fn resume_stops_after_write_to_watched_cell() {
+        // given
+        let mut debugger = Debugger::new(State::new(COUNTDOWN.to_vec()));
+        debugger.input.push_back(5);
+        debugger.watchpoints.insert(12);
+
+        // when
+        let stop1 = debugger.resume(None).expect("Expected valid run");
+        let stop2 = debugger.resume(None).expect("Expected valid run");
+
+        // then
+        assert_eq!(
+            stop1,
+            Stop::Watchpoint {
+                address: 12,
+                old: 0,
+                new: 5
+            }
+        );
+        assert_eq!(
+            stop2,
+            Stop::Watchpoint {
+                address: 12,
+                old: 5,
+                new: 4
+            }
+        );
+        assert_eq!(debugger.state.ip, 8);
+        assert_eq!(debugger.output, vec![5]);
+    }
+
+    #[test]
+    fn step_reports_executed_instruction() {
+        // given
+        let mut debugger = Debugger::new(State::new(COUNTDOWN.to_vec()));
+        debugger.input.push_back(1);
+        debugger.state.rel_base = 3;
+
+        // when
+        let (entry1, stop1) = debugger.step().expect("Expected valid step");
+        let (entry2, stop2) = debugger.step().expect("Expected valid step");
+        let stop3 = debugger.resume(Some(2)).expect("Expected valid run");
+
+        // then
+        assert_eq!(entry1.to_string(), "     0: IN [12] | rb=3 [12]<-1 in=1");
+        assert_eq!(entry2.to_string(), "     2: OUT [12] | rb=3 [12]=1 out=1");
+        assert_eq!(stop1, Stop::Step);
+        assert_eq!(stop2, Stop::Step);
+        assert_eq!(stop3, Stop::OutOfFuel);
+        assert_eq!(debugger.state.ip, 11);
+    }
+
+    #[test]
+    fn step_back_restores_state_and_io() {
+        // given
+        let mut debugger = Debugger::new(State::new(COUNTDOWN.to_vec()));
+        debugger.journal = Some(Journal::new(3, 10));
+        debugger.input.extend(vec![2, 7]);
+        debugger.resume(None).expect("Expected valid run");
+        let halted = debugger.clone();
+
+        // when
+        let undone1 = debugger.rewind_to_io();
+        let state_before_output = debugger.state.clone();
+        let undone2 = debugger.step_back(100);
+
+        // then
+        assert_eq!(halted.output, vec![2, 1]);
+        assert_eq!(undone1, 3);
+        assert_eq!(state_before_output.ip, 2);
+        assert_eq!(undone2, 4);
+        assert_eq!(debugger.state, State::new(COUNTDOWN.to_vec()));
+        assert_eq!(debugger.input, vec![2, 7]);
+        assert!(debugger.output.is_empty());
+        debugger.resume(None).expect("Expected valid run");
+        assert_eq!(debugger.state, halted.state);
+        assert_eq!(debugger.output, halted.output);
+    }
+
+    #[test]
+    fn step_back_does_not_return_to_replaced_state() {
+        // given
+        let mut debugger = Debugger::new(State::new(COUNTDOWN.to_vec()));
+        debugger.journal = Some(Journal::new(3, 10));
+        debugger.input.push_back(2);
+        debugger.resume(None).expect("Expected valid run");
+        let mut loaded = State::new(COUNTDOWN.to_vec());
+        loaded.mem.write(12, 9).expect("Expected valid write");
+
+        // when
+        debugger.set_state(loaded.clone());
+        let undone1 = debugger.step_back(5);
+        debugger.input.push_back(4);
+        debugger.step().expect("Expected valid step");
+        let undone2 = debugger.step_back(5);
+
+        // then
+        assert_eq!(undone1, 0);
+        assert_eq!(undone2, 1);
+        assert_eq!(debugger.state, loaded);
+        assert_eq!(debugger.input, vec![4]);
+    }
+}
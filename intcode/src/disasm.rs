@@ -0,0 +1,142 @@
+use crate::{Cell, Instruction, Mode};
+use std::fmt::{Display, Formatter};
+
+// One line of a disassembly listing
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct Line {
+    pub address: usize,
+    // the raw memory words covered by this line
+    pub words: Vec<isize>,
+    // `None` if the line is rendered as data, i.e. the word is not a valid instruction or the
+    // instruction does not fit into the program anymore
+    pub instruction: Option<Instruction>,
+}
+
+impl Line {
+    // Renders the instruction (or data word) without address, e.g. "ADD [12], #5, rb+3"
+    pub fn code(&self) -> String {
+        match self.instruction {
+            Some(instruction) => format_instruction(&instruction, &self.words[1..]),
+            None => format!("DATA {}", self.words[0]),
+        }
+    }
+}
+
+// Renders the line in the listing format: address, instruction and the raw words as comment.
+// The assembler reads this format.
+impl Display for Line {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        let code = format!("{:>6}: {}", self.address, self.code());
+        let words: Vec<String> = self.words.iter().map(|w| w.to_string()).collect();
+        write!(f, "{:<40} ; {}", code, words.join(","))
+    }
+}
+
+// Renders an instruction with its raw parameter words, e.g. "ADD [12], #5, rb+3"
+pub(crate) fn format_instruction<C: Cell>(instruction: &Instruction, params: &[C]) -> String {
+    let params: Vec<String> = instruction
+        .modes
+        .iter()
+        .zip(params)
+        .map(|(mode, value)| Param(*mode, value).to_string())
+        .collect();
+    if params.is_empty() {
+        instruction.opcode.mnemonic().to_owned()
+    } else {
+        format!("{} {}", instruction.opcode.mnemonic(), params.join(", "))
+    }
+}
+
+struct Param<'a, C>(Mode, &'a C);
+
+impl<'a, C: Cell> Display for Param<'a, C> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Param(Mode::Position, value) => write!(f, "[{}]", value),
+            Param(Mode::Immediate, value) => write!(f, "#{}", value),
+            Param(Mode::Relative, value) if **value < C::zero() => write!(f, "rb{}", value),
+            Param(Mode::Relative, value) => write!(f, "rb+{}", value),
+        }
+    }
+}
+
+// Decode the whole program linearly. Words that can not be decoded as instructions are
+// rendered as data, decoding continues at the next word.
+pub fn disassemble(program: &[isize]) -> Vec<Line> {
+    let mut lines: Vec<Line> = Vec::with_capacity(program.len());
+    let mut address: usize = 0;
+    while address < program.len() {
+        let instruction = Instruction::decode(address, program[address])
+            .ok()
+            .filter(|instruction| address + instruction.size() <= program.len());
+        let size = instruction.map(|i| i.size()).unwrap_or(1);
+        lines.push(Line {
+            address,
+            words: program[address..address + size].to_vec(),
+            instruction,
+        });
+        address += size;
+    }
+    lines
+}
+
+pub fn listing(program: &[isize]) -> String {
+    let mut result = String::with_capacity(program.len() * 32);
+    for line in disassemble(program) {
+        result.push_str(&line.to_string());
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn disassemble_decodes_instructions_and_data() {
+        // given
+        let program = &[21_001, 12, 5, 3, 99, 42, 7];
+
+        // when
+        let lines = disassemble(program);
+
+        // then
+        let code: Vec<(usize, String)> = lines.iter().map(|l| (l.address, l.code())).collect();
+        assert_eq!(
+            code,
+            vec![
+                (0, "ADD [12], #5, rb+3".to_owned()),
+                (4, "HLT".to_owned()),
+                (5, "DATA 42".to_owned()),
+                (6, "DATA 7".to_owned()),
+            ]
+        );
+        assert_eq!(lines[0].words, vec![21_001, 12, 5, 3]);
+        assert_eq!(lines[2].instruction, None);
+    }
+
+    #[test]
+    fn disassemble_renders_truncated_instruction_as_data() {
+        // when
+        let lines = disassemble(&[99, 1_101, 2]);
+
+        // then
+        let code: Vec<String> = lines.iter().map(|l| l.code()).collect();
+        assert_eq!(code, vec!["HLT", "DATA 1101", "DATA 2"]);
+    }
+
+    #[test]
+    fn listing_contains_addresses_and_raw_words() {
+        // when
+        let listing = listing(&[204, -3, 99]);
+
+        // then
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("     0: OUT rb-3 "));
+        assert!(lines[0].ends_with("; 204,-3"));
+        assert!(lines[1].starts_with("     2: HLT "));
+        assert!(lines[1].ends_with("; 99"));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{DenseMemory, Memory, State};
+use std::error::Error;
+use std::fmt::Debug;
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum IntcodeError {
+    // the opcode at `address` (full instruction word: `value`) is not known
+    UnknownOpcode {
+        address: usize,
+        value: isize,
+    },
+    // the instruction at `address` uses a parameter mode that is not known
+    InvalidMode {
+        address: usize,
+        mode: isize,
+    },
+    // an address calculated from the operands (including the relative base) is negative
+    NegativeAddress {
+        address: isize,
+    },
+    // the instruction at `address` needs more operands than there is memory left
+    MissingOperands {
+        address: usize,
+        mem_size: usize,
+    },
+    // the program tried to write to an address above the highest address of its memory
+    AddressOutOfRange {
+        address: usize,
+        max_address: usize,
+    },
+    // an addition or multiplication in the instruction at `address` overflowed
+    Overflow {
+        address: usize,
+    },
+    // a value that is used as instruction, address, relative base or jump target is too large
+    ValueOutOfRange {
+        value: String,
+    },
+    // the instruction pointer left the program without reaching a halt instruction
+    IpOutOfBounds {
+        ip: usize,
+        mem_size: usize,
+    },
+    // the instruction at `address` tried to write to `target`, which was executed before. Only
+    // reported by `CodeGuard` with `CodeWritePolicy::Error`.
+    SelfModifyingWrite {
+        address: usize,
+        target: usize,
+    },
+    // the machine waits for input, but all machines it could get input from wait as well or
+    // have stopped. Only reported by `Cluster`.
+    Deadlock,
+    // the token with the (zero-based) index `index` is not a valid number
+    Parse {
+        index: usize,
+        token: String,
+        message: String,
+    },
+}
+
+impl Display for IntcodeError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            IntcodeError::UnknownOpcode { address, value } => {
+                write!(f, "Unknown opcode {} at address {}", value, address)
+            }
+            IntcodeError::InvalidMode { address, mode } => {
+                write!(f, "Unknown mode {} at address {}", mode, address)
+            }
+            IntcodeError::NegativeAddress { address } => {
+                write!(f, "memory index {} is out of bounds", address)
+            }
+            IntcodeError::MissingOperands { address, mem_size } => write!(
+                f,
+                "Not enough operands for ip {} and mem.len() {}",
+                address, mem_size
+            ),
+            IntcodeError::AddressOutOfRange {
+                address,
+                max_address,
+            } => write!(
+                f,
+                "memory index {} is out of bounds (max address: {})",
+                address, max_address
+            ),
+            IntcodeError::Overflow { address } => {
+                write!(f, "Arithmetic overflow at address {}", address)
+            }
+            IntcodeError::ValueOutOfRange { value } => {
+                write!(
+                    f,
+                    "value {} is too large to be an address or instruction",
+                    value
+                )
+            }
+            IntcodeError::IpOutOfBounds { ip, mem_size } => write!(
+                f,
+                "Program did not halt: ip {} is out of bounds (memsize: {})",
+                ip, mem_size
+            ),
+            IntcodeError::SelfModifyingWrite { address, target } => write!(
+                f,
+                "Instruction at address {} writes to executed code at address {}",
+                address, target
+            ),
+            IntcodeError::Deadlock => write!(
+                f,
+                "Deadlock: the process is waiting for input, but no other process can send any"
+            ),
+            IntcodeError::Parse {
+                index,
+                token,
+                message,
+            } => write!(
+                f,
+                "Unable to parse token {} ('{}'): {}",
+                index, token, message
+            ),
+        }
+    }
+}
+
+impl Error for IntcodeError {}
+
+impl From<IntcodeError> for String {
+    fn from(error: IntcodeError) -> String {
+        error.to_string()
+    }
+}
+
+// An error that occured while running a program, together with the state of the machine at the
+// point of failure. The instruction pointer still points to the instruction that failed, so
+// after fixing the cause (e.g. patching the memory), the state can be used to resume the program.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct RunError<M: Memory = DenseMemory> {
+    pub error: IntcodeError,
+    pub state: State<M>,
+    // output that was produced before the error occured
+    pub output: Vec<M::Cell>,
+}
+
+impl<M: Memory> Display for RunError<M> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "{} (ip: {}, relative base: {})",
+            self.error, self.state.ip, self.state.rel_base
+        )
+    }
+}
+
+impl<M: Memory + Debug> Error for RunError<M> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<M: Memory> From<RunError<M>> for String {
+    fn from(error: RunError<M>) -> String {
+        error.to_string()
+    }
+}
@@ -0,0 +1,293 @@
+// A faster engine with the same semantics as `run_program`.
+//
+// `State::step_with` decodes the instruction word (divisions for the modes) and checks the
+// operand count on every step. `FastMachine` decodes each address once and caches the opcode,
+// the modes and the raw parameters. A write invalidates all cached instructions that contain
+// the written cell, so self-modifying programs still work.
+//
+// Everything unusual (invalid instructions, errors, parameters that do not fit into an isize)
+// is left to `State::step_with`, so errors and the state after them are exactly the same.
+use crate::io::{at_halt, run_steps};
+use crate::step::bool_cell;
+use crate::{
+    Cell, DenseMemory, Instruction, IntcodeError, IntcodeInput, IntcodeOutput, Limits, Memory,
+    Mode, Opcode, ReturnStatus, RunError, RunResult, State, StepEvent,
+};
+
+// Only the first addresses are cached, so a sparse memory with a huge length does not
+// allocate a huge cache. Instructions behind this are executed by `State::step_with`.
+const MAX_CACHED_ADDRESS: usize = 1 << 20;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+struct Decoded {
+    opcode: Opcode,
+    modes: [Mode; 3],
+    params: [isize; 3],
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct FastMachine<M: Memory = DenseMemory> {
+    state: State<M>,
+    // decoded instruction by address, `None` if not decoded yet or not decodable
+    cache: Vec<Option<Decoded>>,
+}
+
+impl<M: Memory> FastMachine<M> {
+    pub fn new(state: State<M>) -> FastMachine<M> {
+        let cache = vec![None; state.mem.len().min(MAX_CACHED_ADDRESS)];
+        FastMachine { state, cache }
+    }
+
+    // The state can only be read: writing to the memory directly would bypass the cache.
+    pub fn state(&self) -> &State<M> {
+        &self.state
+    }
+
+    pub fn into_state(self) -> State<M> {
+        self.state
+    }
+
+    // Execute exactly one instruction, like `State::step_with`.
+    pub fn step_with<I>(&mut self, input: &mut I) -> Result<StepEvent<M::Cell>, IntcodeError>
+    where
+        I: IntcodeInput<M::Cell> + ?Sized,
+    {
+        let ip = self.state.ip;
+        let decoded = match self.cache.get(ip) {
+            Some(Some(decoded)) => Some(*decoded),
+            Some(None) => {
+                self.cache[ip] = self.decode(ip);
+                self.cache[ip]
+            }
+            None => None,
+        };
+        if let Some(event) = decoded.and_then(|decoded| self.execute(&decoded, input)) {
+            return Ok(event);
+        }
+        let step = self.state.step_with(input)?;
+        if let Some((address, _)) = &step.write {
+            self.invalidate(*address);
+        }
+        Ok(step.event)
+    }
+
+    // Like `run`, but with the fast engine
+    pub fn run<I, O>(&mut self, input: &mut I, output: &mut O) -> Result<ReturnStatus, IntcodeError>
+    where
+        I: IntcodeInput<M::Cell> + ?Sized,
+        O: IntcodeOutput<M::Cell> + ?Sized,
+    {
+        self.run_limited(input, output, &Limits::unlimited())
+    }
+
+    // Like `run_limited`, but with the fast engine
+    pub fn run_limited<I, O>(
+        &mut self,
+        input: &mut I,
+        output: &mut O,
+        limits: &Limits,
+    ) -> Result<ReturnStatus, IntcodeError>
+    where
+        I: IntcodeInput<M::Cell> + ?Sized,
+        O: IntcodeOutput<M::Cell> + ?Sized,
+    {
+        run_steps(
+            self,
+            output,
+            limits,
+            |machine| at_halt(&machine.state),
+            |machine| machine.step_with(input),
+        )
+    }
+
+    // `None` if the instruction at `ip` would fail or its parameters do not fit into an isize
+    fn decode(&self, ip: usize) -> Option<Decoded> {
+        let mem = &self.state.mem;
+        let instruction = Instruction::decode(ip, mem.read(ip).to_isize()?).ok()?;
+        let count = instruction.opcode.param_count();
+        if ip + count >= mem.len() {
+            return None;
+        }
+        let mut params = [0; 3];
+        for (n, param) in params.iter_mut().enumerate().take(count) {
+            *param = mem.read(ip + n + 1).to_isize()?;
+        }
+        Some(Decoded {
+            opcode: instruction.opcode,
+            modes: instruction.modes,
+            params,
+        })
+    }
+
+    // Execute a decoded instruction. Returns `None` without changing anything if the
+    // instruction needs the slow path.
+    fn execute<I>(&mut self, decoded: &Decoded, input: &mut I) -> Option<StepEvent<M::Cell>>
+    where
+        I: IntcodeInput<M::Cell> + ?Sized,
+    {
+        let event = match decoded.opcode {
+            Opcode::Add => {
+                let value = self.param(decoded, 0)?.try_add(&self.param(decoded, 1)?)?;
+                self.write(decoded, 2, value)?
+            }
+            Opcode::Mul => {
+                let value = self.param(decoded, 0)?.try_mul(&self.param(decoded, 1)?)?;
+                self.write(decoded, 2, value)?
+            }
+            Opcode::Lt => {
+                let value = bool_cell(self.param(decoded, 0)? < self.param(decoded, 1)?);
+                self.write(decoded, 2, value)?
+            }
+            Opcode::Eq => {
+                let value = bool_cell(self.param(decoded, 0)? == self.param(decoded, 1)?);
+                self.write(decoded, 2, value)?
+            }
+            Opcode::In => {
+                self.dest(decoded, 0)?;
+                match input.read() {
+                    Some(value) => {
+                        self.write(decoded, 0, value.clone())?;
+                        StepEvent::Input(value)
+                    }
+                    None => return Some(StepEvent::InputRequired),
+                }
+            }
+            Opcode::Out => StepEvent::Output(self.param(decoded, 0)?),
+            Opcode::Jnz | Opcode::Jz => {
+                let condition = self.param(decoded, 0)? != M::Cell::zero();
+                let target = self.param(decoded, 1)?;
+                if condition == (decoded.opcode == Opcode::Jnz) {
+                    let target = target.to_isize()?;
+                    if target < 0 {
+                        return None;
+                    }
+                    self.state.ip = target as usize;
+                    return Some(StepEvent::Continue);
+                }
+                StepEvent::Continue
+            }
+            Opcode::Arb => {
+                let offset = self.param(decoded, 0)?.to_isize()?;
+                self.state.rel_base = self.state.rel_base.checked_add(offset)?;
+                StepEvent::Continue
+            }
+            Opcode::Hlt => return Some(StepEvent::Halt),
+        };
+        self.state.ip += decoded.opcode.param_count() + 1;
+        Some(event)
+    }
+
+    // value of the nth parameter, `None` for negative addresses
+    fn param(&self, decoded: &Decoded, n: usize) -> Option<M::Cell> {
+        let raw_value = decoded.params[n];
+        let address = match decoded.modes[n] {
+            Mode::Immediate => return Some(M::Cell::from_isize(raw_value)),
+            Mode::Position => raw_value,
+            Mode::Relative => raw_value.checked_add(self.state.rel_base)?,
+        };
+        if address < 0 {
+            return None;
+        }
+        Some(self.state.mem.read(address as usize))
+    }
+
+    // address of the nth parameter, `None` if it can not be written to
+    fn dest(&self, decoded: &Decoded, n: usize) -> Option<usize> {
+        let raw_address = decoded.params[n];
+        let address = match decoded.modes[n] {
+            Mode::Relative => raw_address.checked_add(self.state.rel_base)?,
+            _ => raw_address,
+        };
+        if address < 0 {
+            return None;
+        }
+        self.state.mem.check_address(address as usize).ok()?;
+        Some(address as usize)
+    }
+
+    fn write(&mut self, decoded: &Decoded, n: usize, value: M::Cell) -> Option<StepEvent<M::Cell>> {
+        let address = self.dest(decoded, n)?;
+        self.state.mem.write(address, value).ok()?;
+        self.invalidate(address);
+        Some(StepEvent::Continue)
+    }
+
+    // forget all cached instructions that contain `address`
+    fn invalidate(&mut self, address: usize) {
+        let end = (address + 1).min(self.cache.len());
+        for entry in &mut self.cache[address.saturating_sub(3).min(end)..end] {
+            *entry = None;
+        }
+    }
+}
+
+// Like `run_program`, but with the fast engine
+pub fn run_program_fast<M: Memory>(state: State<M>, input: &[M::Cell]) -> RunResult<M> {
+    let mut machine = FastMachine::new(state);
+    let mut output: Vec<M::Cell> = Vec::new();
+    let result = machine.run(&mut &input[..], &mut output);
+    match result {
+        Ok(status) => Ok((machine.state, status, output)),
+        Err(error) => Err(RunError {
+            error,
+            state: machine.state,
+            output,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::run_program;
+    use crate::test_programs::COUNTER;
+
+    // day 9: outputs a copy of itself
+    const QUINE: &[isize] = &[
+        109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+    ];
+
+    #[test]
+    fn run_program_fast_matches_run_program() {
+        // given
+        let programs: Vec<(Vec<isize>, Vec<isize>)> = vec![
+            (COUNTER.to_vec(), vec![]),
+            (QUINE.to_vec(), vec![]),
+            // day 5: compares the input with 8
+            (vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], vec![8]),
+            // overwrites its own halt instruction with an unknown opcode
+            (vec![1101, 40, 2, 5, 4, 99], vec![]),
+            // a jump to a negative address
+            (vec![1105, 1, -1], vec![]),
+            // waits for input
+            (vec![3, 3, 99], vec![]),
+        ];
+
+        for (program, input) in programs {
+            // when
+            let expected = run_program(State::new(program.clone()), &input);
+            let actual = run_program_fast(State::new(program.clone()), &input);
+
+            // then
+            assert_eq!(actual, expected, "program {:?}", program);
+        }
+    }
+
+    #[test]
+    fn fast_machine_can_be_resumed_with_limits() {
+        // given
+        let mut machine = FastMachine::new(State::new(COUNTER.to_vec()));
+        let mut output: Vec<isize> = Vec::new();
+        let limits = Limits::instructions(5);
+
+        // when
+        let status1 = machine.run_limited(&mut None, &mut output, &limits);
+        let status2 = machine.run(&mut None, &mut output);
+
+        // then
+        assert_eq!(status1, Ok(ReturnStatus::OutOfFuel));
+        assert_eq!(status2, Ok(ReturnStatus::Halt));
+        assert_eq!(output, vec![0, 1, 2]);
+        assert_eq!(machine.state().mem.read(1), 3);
+    }
+}
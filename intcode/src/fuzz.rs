@@ -0,0 +1,425 @@
+// A fuzz harness for the interpreter: generates random programs and checks invariants that
+// must hold for every program, valid or not:
+// - running a program never panics
+// - writing to a huge address fails with an error instead of allocating the memory
+// - every engine (fast, paged memory, guarded, recorded) gives exactly the same result as the
+//   reference `run_limited` on a dense memory: status or error, output and final state
+// - feeding the input one value at a time, resuming after every `Wait`, gives the same result
+//   as a single run with all input up front
+//
+// Programs are well-formed (valid instructions with addresses inside the program), mutated
+// (some words replaced by edge values) or random words. Runs are limited in fuel, so endless
+// loops are fine. Every case is reproducible from its seed.
+use crate::network::Rng;
+use crate::{
+    run_guarded, run_limited, CodeGuard, CodeWritePolicy, DenseMemory, FastMachine, IntcodeError,
+    Limits, Memory, Opcode, PagedMemory, Recorder, ReturnStatus, State,
+};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::panic::catch_unwind;
+
+// The memory limit of the fuzzed machines, writes above it must fail
+const MAX_ADDRESS: usize = (1 << 16) - 1;
+
+// values that tend to find bugs: limits of the types, addresses above `MAX_ADDRESS` and valid
+// instruction words
+const EDGE_VALUES: &[isize] = &[
+    0,
+    -1,
+    1,
+    99,
+    109,
+    203,
+    21_101,
+    isize::MAX,
+    isize::MIN,
+    isize::MAX / 2 + 1,
+    (MAX_ADDRESS + 1) as isize,
+    1 << 40,
+    -(1 << 40),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct FuzzConfig {
+    pub cases: u64,
+    pub seed: u64,
+    // fuel of a single run
+    pub max_instructions: u64,
+}
+
+impl Default for FuzzConfig {
+    fn default() -> FuzzConfig {
+        FuzzConfig {
+            cases: 1000,
+            seed: 0,
+            max_instructions: 2000,
+        }
+    }
+}
+
+// A program and input that break an invariant. The program and input are shrunk as far as
+// possible while the case still fails.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct FuzzFailure {
+    // seed of the case, `fuzz_case` generates the same program and input again
+    pub case_seed: u64,
+    pub program: Vec<isize>,
+    pub input: Vec<isize>,
+    pub message: String,
+}
+
+impl Display for FuzzFailure {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        let join = |values: &[isize]| -> String {
+            let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            values.join(",")
+        };
+        write!(
+            f,
+            "{} (case seed {})\nprogram: {}\ninput: {}",
+            self.message,
+            self.case_seed,
+            join(&self.program),
+            join(&self.input)
+        )
+    }
+}
+
+impl Error for FuzzFailure {}
+
+impl From<FuzzFailure> for String {
+    fn from(failure: FuzzFailure) -> String {
+        failure.to_string()
+    }
+}
+
+// Everything observable about a run
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Outcome {
+    result: Result<ReturnStatus, IntcodeError>,
+    output: Vec<isize>,
+    ip: usize,
+    rel_base: isize,
+    mem: Vec<isize>,
+}
+
+impl Outcome {
+    fn new<M: Memory<Cell = isize>>(
+        result: Result<ReturnStatus, IntcodeError>,
+        output: Vec<isize>,
+        state: &State<M>,
+    ) -> Outcome {
+        Outcome {
+            result,
+            output,
+            ip: state.ip,
+            rel_base: state.rel_base,
+            mem: state.mem.to_vec(),
+        }
+    }
+}
+
+type Engine = fn(&[isize], &[isize], &Limits) -> Outcome;
+
+fn dense(program: &[isize]) -> State {
+    State::with_memory(DenseMemory::with_max_address(program.to_vec(), MAX_ADDRESS))
+}
+
+fn reference(program: &[isize], input: &[isize], limits: &Limits) -> Outcome {
+    let mut state = dense(program);
+    let mut output = Vec::new();
+    let result = run_limited(&mut state, &mut &input[..], &mut output, limits);
+    Outcome::new(result, output, &state)
+}
+
+const ENGINES: &[(&str, Engine)] = &[
+    ("fast", |program, input, limits| {
+        let mut machine = FastMachine::new(dense(program));
+        let mut output = Vec::new();
+        let result = machine.run_limited(&mut &input[..], &mut output, limits);
+        Outcome::new(result, output, machine.state())
+    }),
+    ("paged", |program, input, limits| {
+        let memory = PagedMemory::with_max_address(program, MAX_ADDRESS);
+        let mut state = State::with_memory(memory);
+        let mut output = Vec::new();
+        let result = run_limited(&mut state, &mut &input[..], &mut output, limits);
+        Outcome::new(result, output, &state)
+    }),
+    ("guarded", |program, input, limits| {
+        let mut state = dense(program);
+        let mut guard = CodeGuard::new(CodeWritePolicy::Warn);
+        let mut output = Vec::new();
+        let result = run_guarded(&mut state, &mut &input[..], &mut output, limits, &mut guard);
+        Outcome::new(result, output, &state)
+    }),
+    ("recorded", |program, input, limits| {
+        let mut recorder = Recorder::new(dense(program));
+        let mut output = Vec::new();
+        let result = recorder.run_limited(&mut &input[..], &mut output, limits);
+        Outcome::new(result, output, recorder.state())
+    }),
+];
+
+// the input one value at a time: every `Wait` gets the next value
+fn resumed(program: &[isize], input: &[isize], limits: &Limits) -> Outcome {
+    let mut state = dense(program);
+    let mut queue: VecDeque<isize> = VecDeque::new();
+    let mut remaining = input.iter();
+    let mut output = Vec::new();
+    loop {
+        let result = run_limited(&mut state, &mut queue, &mut output, limits);
+        match (&result, remaining.next()) {
+            (Ok(ReturnStatus::Wait), Some(value)) => queue.push_back(*value),
+            _ => return Outcome::new(result, output, &state),
+        }
+    }
+}
+
+// Check all invariants for one program and input, the error describes the first violation
+pub fn check_program(
+    program: &[isize],
+    input: &[isize],
+    max_instructions: u64,
+) -> Result<(), String> {
+    let limits = Limits::instructions(max_instructions);
+    let run = |name: &str, engine: Engine| -> Result<Outcome, String> {
+        catch_unwind(|| engine(program, input, &limits)).map_err(|_| format!("{} panicked", name))
+    };
+    let expected = run("reference", reference)?;
+    if expected.mem.len() > MAX_ADDRESS + 1 {
+        return Err(format!(
+            "memory grew to {} cells, above the limit of {}",
+            expected.mem.len(),
+            MAX_ADDRESS + 1
+        ));
+    }
+    for (name, engine) in ENGINES {
+        let actual = run(name, *engine)?;
+        if actual != expected {
+            return Err(format!(
+                "engine '{}' differs from the reference: {}",
+                name,
+                difference(&expected, &actual)
+            ));
+        }
+    }
+    // resuming gets new fuel for every run, so it is only comparable if the reference finished
+    if expected.result != Ok(ReturnStatus::OutOfFuel) {
+        let actual = run("resumed", resumed)?;
+        if actual != expected {
+            return Err(format!(
+                "resuming after every input differs from a single run: {}",
+                difference(&expected, &actual)
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn difference(expected: &Outcome, actual: &Outcome) -> String {
+    if expected.result != actual.result {
+        format!("expected {:?}, got {:?}", expected.result, actual.result)
+    } else if expected.output != actual.output {
+        format!(
+            "expected output {:?}, got {:?}",
+            expected.output, actual.output
+        )
+    } else if (expected.ip, expected.rel_base) != (actual.ip, actual.rel_base) {
+        format!(
+            "expected ip {} and relative base {}, got {} and {}",
+            expected.ip, expected.rel_base, actual.ip, actual.rel_base
+        )
+    } else {
+        let address = (0..expected.mem.len().max(actual.mem.len()))
+            .find(|a| expected.mem.get(*a) != actual.mem.get(*a))
+            .unwrap_or(0);
+        format!(
+            "memory differs at address {}: expected {:?}, got {:?}",
+            address,
+            expected.mem.get(address),
+            actual.mem.get(address)
+        )
+    }
+}
+
+// The program and input of the case with the given seed
+pub fn fuzz_case(case_seed: u64) -> (Vec<isize>, Vec<isize>) {
+    let mut rng = Rng::new(case_seed);
+    let mut program = match rng.below(4) {
+        0 => random_words(&mut rng),
+        1 => {
+            let mut program = well_formed(&mut rng);
+            for _ in 0..=rng.below(3) {
+                let address = rng.below(program.len() as u64) as usize;
+                program[address] = edge_value(&mut rng);
+            }
+            program
+        }
+        _ => well_formed(&mut rng),
+    };
+    if program.is_empty() {
+        program.push(99);
+    }
+    let input = (0..rng.below(8))
+        .map(|_| match rng.below(10) {
+            0 => edge_value(&mut rng),
+            _ => rng.below(24) as isize - 4,
+        })
+        .collect();
+    (program, input)
+}
+
+fn edge_value(rng: &mut Rng) -> isize {
+    EDGE_VALUES[rng.below(EDGE_VALUES.len() as u64) as usize]
+}
+
+fn random_words(rng: &mut Rng) -> Vec<isize> {
+    (0..rng.below(32))
+        .map(|_| match rng.below(4) {
+            0 => edge_value(rng),
+            _ => rng.below(230) as isize - 10,
+        })
+        .collect()
+}
+
+// Valid instructions with addresses near the program, followed by a halt and some data
+fn well_formed(rng: &mut Rng) -> Vec<isize> {
+    let instruction_count = 1 + rng.below(12) as usize;
+    // an estimate of the program size for the addresses
+    let size = instruction_count as u64 * 3 + 8;
+    let mut program: Vec<isize> = Vec::new();
+    for _ in 0..instruction_count {
+        let opcode = Opcode::ALL[rng.below(Opcode::ALL.len() as u64 - 1) as usize];
+        let mut word = opcode.code();
+        let mut params = Vec::new();
+        for n in 0..opcode.param_count() {
+            let writes = n + 1 == opcode.param_count()
+                && matches!(
+                    opcode,
+                    Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq | Opcode::In
+                );
+            let mode = match rng.below(3) {
+                1 if writes => 0,
+                mode => mode as isize,
+            };
+            word += mode * [100, 1000, 10_000][n];
+            params.push(match mode {
+                0 => rng.below(size) as isize,
+                1 => rng.below(size + 4) as isize - 4,
+                _ => rng.below(12) as isize - 4,
+            });
+        }
+        program.push(word);
+        program.extend(params);
+    }
+    program.push(99);
+    for _ in 0..rng.below(6) {
+        program.push(rng.below(size) as isize);
+    }
+    program
+}
+
+// Remove program words and input values as long as the case keeps failing
+fn shrink(program: &mut Vec<isize>, input: &mut Vec<isize>, max_instructions: u64) -> String {
+    let mut message = match check_program(program, input, max_instructions) {
+        Err(message) => message,
+        Ok(()) => return String::new(),
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in (0..input.len()).rev() {
+            let mut candidate = input.clone();
+            candidate.remove(i);
+            if let Err(m) = check_program(program, &candidate, max_instructions) {
+                *input = candidate;
+                message = m;
+                changed = true;
+            }
+        }
+        for i in (0..program.len()).rev() {
+            let mut candidate = program.clone();
+            candidate.remove(i);
+            if candidate.is_empty() {
+                continue;
+            }
+            if let Err(m) = check_program(&candidate, input, max_instructions) {
+                *program = candidate;
+                message = m;
+                changed = true;
+            }
+        }
+    }
+    message
+}
+
+// Run `config.cases` random cases, returns the first failure (shrunk)
+pub fn fuzz(config: &FuzzConfig) -> Result<(), FuzzFailure> {
+    let mut rng = Rng::new(config.seed);
+    for _ in 0..config.cases {
+        let case_seed = rng.next_u64();
+        let (mut program, mut input) = fuzz_case(case_seed);
+        if check_program(&program, &input, config.max_instructions).is_err() {
+            let message = shrink(&mut program, &mut input, config.max_instructions);
+            return Err(FuzzFailure {
+                case_seed,
+                program,
+                input,
+                message,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fuzzed_programs_keep_all_invariants() {
+        // given
+        let config = FuzzConfig {
+            cases: 500,
+            seed: 2019,
+            max_instructions: 500,
+        };
+
+        // when
+        let result = fuzz(&config);
+
+        // then
+        if let Err(failure) = result {
+            panic!("{}", failure);
+        }
+    }
+
+    #[test]
+    fn edge_cases_keep_all_invariants() {
+        // given
+        let cases: Vec<(Vec<isize>, Vec<isize>)> = vec![
+            // writes far above the memory limit
+            (vec![1101, 1, 1, 1 << 40, 99], vec![]),
+            (vec![3, 1 << 20, 99], vec![5]),
+            // relative base overflow
+            (vec![109, isize::MAX, 109, 1, 99], vec![]),
+            // overwrites the instruction it is about to execute
+            (vec![1101, 2, 2, 4, 98, 0, 99], vec![]),
+            // reads input in a loop, outputs it, until the input is 0
+            (vec![3, 9, 4, 9, 1005, 9, 0, 99, 0, 0], vec![1, 2, 0, 7]),
+            // an instruction that is cut off at the end
+            (vec![1, 0, 0], vec![]),
+        ];
+
+        for (program, input) in cases {
+            // when
+            let result = check_program(&program, &input, 1000);
+
+            // then
+            assert_eq!(result, Ok(()), "program {:?}", program);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+// Detection of self-modifying code and a map of the memory a program actually uses.
+//
+// Intcode does not separate code from data, so a stray write into an instruction silently
+// changes the program. `CodeGuard` remembers every cell that was part of an executed
+// instruction (the instruction word and its parameters) and flags later writes to these cells.
+use crate::io::{at_halt, run_steps};
+use crate::{
+    IntcodeError, IntcodeInput, IntcodeOutput, Limits, Memory, ReturnStatus, State, Step, StepEvent,
+};
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum CodeWritePolicy {
+    // the write is executed and recorded in `CodeGuard::code_writes`
+    Warn,
+    // the instruction fails with `IntcodeError::SelfModifyingWrite` before anything is written
+    Error,
+}
+
+// A write into a cell that was executed before
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct CodeWrite<C = isize> {
+    // address of the writing instruction
+    pub address: usize,
+    pub target: usize,
+    pub old: C,
+    pub new: C,
+}
+
+// How a memory cell was used, displayed like file permissions, e.g. "rw-" or "--x"
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
+pub struct Access {
+    pub read: bool,
+    pub written: bool,
+    pub executed: bool,
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        let flag = |set: bool, c: char| if set { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}",
+            flag(self.read, 'r'),
+            flag(self.written, 'w'),
+            flag(self.executed, 'x')
+        )
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CodeGuard<C = isize> {
+    pub policy: CodeWritePolicy,
+    // writes into executed cells, only with `CodeWritePolicy::Warn`
+    pub code_writes: Vec<CodeWrite<C>>,
+    read: BTreeSet<usize>,
+    written: BTreeSet<usize>,
+    executed: BTreeSet<usize>,
+}
+
+impl<C: Clone> CodeGuard<C> {
+    pub fn new(policy: CodeWritePolicy) -> CodeGuard<C> {
+        CodeGuard {
+            policy,
+            code_writes: Vec::new(),
+            read: BTreeSet::new(),
+            written: BTreeSet::new(),
+            executed: BTreeSet::new(),
+        }
+    }
+
+    // Execute a single instruction like `State::step_with` and record the memory accesses.
+    pub fn step<M, I>(
+        &mut self,
+        state: &mut State<M>,
+        input: &mut I,
+    ) -> Result<Step<C>, IntcodeError>
+    where
+        M: Memory<Cell = C>,
+        I: IntcodeInput<C> + ?Sized,
+    {
+        let address = state.ip;
+        let old = match state.write_address() {
+            Some(target) if self.executed.contains(&target) => {
+                if self.policy == CodeWritePolicy::Error {
+                    return Err(IntcodeError::SelfModifyingWrite { address, target });
+                }
+                Some(state.mem.read(target))
+            }
+            _ => None,
+        };
+        let step = state.step_with(input)?;
+        if let StepEvent::InputRequired = step.event {
+            return Ok(step);
+        }
+        let size = step.instruction.size();
+        self.executed.extend(address..address + size);
+        self.read
+            .extend(step.reads.iter().flatten().map(|(address, _)| *address));
+        if let Some((target, new)) = &step.write {
+            self.written.insert(*target);
+            if let Some(old) = old {
+                self.code_writes.push(CodeWrite {
+                    address,
+                    target: *target,
+                    old,
+                    new: new.clone(),
+                });
+            }
+        }
+        Ok(step)
+    }
+
+    pub fn read(&self) -> &BTreeSet<usize> {
+        &self.read
+    }
+
+    pub fn written(&self) -> &BTreeSet<usize> {
+        &self.written
+    }
+
+    pub fn executed(&self) -> &BTreeSet<usize> {
+        &self.executed
+    }
+
+    pub fn access(&self, address: usize) -> Access {
+        Access {
+            read: self.read.contains(&address),
+            written: self.written.contains(&address),
+            executed: self.executed.contains(&address),
+        }
+    }
+
+    // Ranges (start, end exclusive) of cells with the same access, in address order. Cells that
+    // were never used are left out.
+    pub fn memory_map(&self) -> Vec<(usize, usize, Access)> {
+        let used: BTreeSet<usize> = self
+            .read
+            .iter()
+            .chain(&self.written)
+            .chain(&self.executed)
+            .cloned()
+            .collect();
+        let mut map: Vec<(usize, usize, Access)> = Vec::new();
+        for address in used {
+            let access = self.access(address);
+            match map.last_mut() {
+                Some((_, end, last)) if *end == address && *last == access => *end += 1,
+                _ => map.push((address, address + 1, access)),
+            }
+        }
+        map
+    }
+}
+
+// Like `run_limited`, but every instruction is executed with `guard`.
+pub fn run_guarded<M, I, O>(
+    state: &mut State<M>,
+    input: &mut I,
+    output: &mut O,
+    limits: &Limits,
+    guard: &mut CodeGuard<M::Cell>,
+) -> Result<ReturnStatus, IntcodeError>
+where
+    M: Memory,
+    I: IntcodeInput<M::Cell> + ?Sized,
+    O: IntcodeOutput<M::Cell> + ?Sized,
+{
+    run_steps(state, output, limits, at_halt, |state| {
+        Ok(guard.step(state, input)?.event)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_programs::COUNTER;
+
+    #[test]
+    fn warn_policy_records_writes_into_executed_code() {
+        // given
+        let mut state = State::new(COUNTER.to_vec());
+        let mut guard = CodeGuard::new(CodeWritePolicy::Warn);
+        let mut output: Vec<isize> = Vec::new();
+
+        // when
+        let status = run_guarded(
+            &mut state,
+            &mut None,
+            &mut output,
+            &Limits::unlimited(),
+            &mut guard,
+        )
+        .expect("Expected valid run");
+
+        // then
+        assert_eq!(status, ReturnStatus::Halt);
+        assert_eq!(output, vec![0, 1, 2]);
+        let writes: Vec<(usize, usize, isize, isize)> = guard
+            .code_writes
+            .iter()
+            .map(|w| (w.address, w.target, w.old, w.new))
+            .collect();
+        assert_eq!(writes, vec![(2, 1, 0, 1), (2, 1, 1, 2), (2, 1, 2, 3)]);
+    }
+
+    #[test]
+    fn error_policy_fails_before_the_write() {
+        // given
+        let mut state = State::new(COUNTER.to_vec());
+        let mut guard = CodeGuard::new(CodeWritePolicy::Error);
+        let mut output: Vec<isize> = Vec::new();
+
+        // when
+        let result = run_guarded(
+            &mut state,
+            &mut None,
+            &mut output,
+            &Limits::unlimited(),
+            &mut guard,
+        );
+
+        // then
+        assert_eq!(
+            result,
+            Err(IntcodeError::SelfModifyingWrite {
+                address: 2,
+                target: 1
+            })
+        );
+        assert_eq!(state.ip, 2);
+        assert_eq!(state.mem.read(1), 0);
+        assert_eq!(output, vec![0]);
+        assert!(guard.code_writes.is_empty());
+    }
+
+    #[test]
+    fn memory_map_merges_cells_with_the_same_access() {
+        // given
+        let mut state = State::new(COUNTER.to_vec());
+        let mut guard = CodeGuard::new(CodeWritePolicy::Warn);
+
+        // when
+        run_guarded(
+            &mut state,
+            &mut None,
+            &mut Vec::new(),
+            &Limits::unlimited(),
+            &mut guard,
+        )
+        .expect("Expected valid run");
+
+        // then
+        let map: Vec<String> = guard
+            .memory_map()
+            .iter()
+            .map(|(start, end, access)| format!("{}..{} {}", start, end, access))
+            .collect();
+        assert_eq!(map, vec!["0..1 --x", "1..2 rwx", "2..14 --x", "14..15 rw-"]);
+        assert_eq!(guard.written().len(), 2);
+    }
+}
@@ -0,0 +1,222 @@
+use crate::IntcodeError;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Opcode {
+    Add,
+    Mul,
+    In,
+    Out,
+    Jnz,
+    Jz,
+    Lt,
+    Eq,
+    Arb,
+    Hlt,
+}
+
+impl Opcode {
+    pub const ALL: [Opcode; 10] = [
+        Opcode::Add,
+        Opcode::Mul,
+        Opcode::In,
+        Opcode::Out,
+        Opcode::Jnz,
+        Opcode::Jz,
+        Opcode::Lt,
+        Opcode::Eq,
+        Opcode::Arb,
+        Opcode::Hlt,
+    ];
+
+    pub fn from_code(code: isize) -> Option<Opcode> {
+        match code {
+            1 => Some(Opcode::Add),
+            2 => Some(Opcode::Mul),
+            3 => Some(Opcode::In),
+            4 => Some(Opcode::Out),
+            5 => Some(Opcode::Jnz),
+            6 => Some(Opcode::Jz),
+            7 => Some(Opcode::Lt),
+            8 => Some(Opcode::Eq),
+            9 => Some(Opcode::Arb),
+            99 => Some(Opcode::Hlt),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> isize {
+        match self {
+            Opcode::Add => 1,
+            Opcode::Mul => 2,
+            Opcode::In => 3,
+            Opcode::Out => 4,
+            Opcode::Jnz => 5,
+            Opcode::Jz => 6,
+            Opcode::Lt => 7,
+            Opcode::Eq => 8,
+            Opcode::Arb => 9,
+            Opcode::Hlt => 99,
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Add => "ADD",
+            Opcode::Mul => "MUL",
+            Opcode::In => "IN",
+            Opcode::Out => "OUT",
+            Opcode::Jnz => "JNZ",
+            Opcode::Jz => "JZ",
+            Opcode::Lt => "LT",
+            Opcode::Eq => "EQ",
+            Opcode::Arb => "ARB",
+            Opcode::Hlt => "HLT",
+        }
+    }
+
+    pub fn param_count(self) -> usize {
+        match self {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq => 3,
+            Opcode::Jnz | Opcode::Jz => 2,
+            Opcode::In | Opcode::Out | Opcode::Arb => 1,
+            Opcode::Hlt => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl Mode {
+    pub fn from_digit(digit: isize) -> Option<Mode> {
+        match digit {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
+        }
+    }
+
+    pub fn digit(self) -> isize {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+}
+
+// A decoded instruction word. Modes of parameters the opcode does not have are always
+// `Mode::Position`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub modes: [Mode; 3],
+}
+
+impl Instruction {
+    // decode the instruction word `word` that is located at `address` (the address is only used
+    // for error reporting)
+    pub fn decode(address: usize, word: isize) -> Result<Instruction, IntcodeError> {
+        let opcode = Opcode::from_code(word % 100)
+            .filter(|opcode| *opcode != Opcode::Hlt || word == 99)
+            .ok_or(IntcodeError::UnknownOpcode {
+                address,
+                value: word,
+            })?;
+
+        let mut modes = [Mode::Position; 3];
+        let mut mode_digits = word / 100;
+        for mode in modes.iter_mut().take(opcode.param_count()) {
+            *mode = Mode::from_digit(mode_digits % 10).ok_or(IntcodeError::InvalidMode {
+                address,
+                mode: mode_digits % 10,
+            })?;
+            mode_digits /= 10;
+        }
+
+        Ok(Instruction { opcode, modes })
+    }
+
+    pub fn encode(&self) -> isize {
+        self.modes
+            .iter()
+            .rev()
+            .fold(0, |acc, mode| acc * 10 + mode.digit())
+            * 100
+            + self.opcode.code()
+    }
+
+    // number of memory cells the instruction occupies (including the parameters)
+    pub fn size(&self) -> usize {
+        self.opcode.param_count() + 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_works_for_all_modes() {
+        // when
+        let instruction = Instruction::decode(0, 21_001).expect("Expected valid instruction");
+
+        // then
+        assert_eq!(instruction.opcode, Opcode::Add);
+        assert_eq!(
+            instruction.modes,
+            [Mode::Position, Mode::Immediate, Mode::Relative]
+        );
+    }
+
+    #[test]
+    fn decode_ignores_modes_of_missing_parameters() {
+        // when
+        let instruction = Instruction::decode(0, 30_104).expect("Expected valid instruction");
+
+        // then
+        assert_eq!(instruction.opcode, Opcode::Out);
+        assert_eq!(
+            instruction.modes,
+            [Mode::Immediate, Mode::Position, Mode::Position]
+        );
+    }
+
+    #[test]
+    fn decode_fails_for_invalid_instructions() {
+        assert_eq!(
+            Instruction::decode(3, 42),
+            Err(IntcodeError::UnknownOpcode {
+                address: 3,
+                value: 42
+            })
+        );
+        assert_eq!(
+            Instruction::decode(3, 199),
+            Err(IntcodeError::UnknownOpcode {
+                address: 3,
+                value: 199
+            })
+        );
+        assert_eq!(
+            Instruction::decode(3, 3_005),
+            Err(IntcodeError::InvalidMode {
+                address: 3,
+                mode: 3
+            })
+        );
+    }
+
+    #[test]
+    fn encode_is_inverse_of_decode() {
+        for word in &[1, 99, 1_002, 21_107, 204, 1_105, 109] {
+            let instruction = Instruction::decode(0, *word).expect("Expected valid instruction");
+            assert_eq!(instruction.encode(), *word);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use crate::{Cell, IntcodeError, Memory, ReturnStatus, State, StepEvent, TraceSink};
+use std::collections::VecDeque;
+
+pub trait IntcodeInput<C = isize> {
+    // Get the next input value. Returning `None` blocks the machine: `run` returns
+    // `ReturnStatus::Wait` and the input instruction is executed again on the next run.
+    fn read(&mut self) -> Option<C>;
+}
+
+pub trait IntcodeOutput<C = isize> {
+    fn write(&mut self, value: C);
+}
+
+// a single input value that is consumed on the first read
+impl<C> IntcodeInput<C> for Option<C> {
+    fn read(&mut self) -> Option<C> {
+        self.take()
+    }
+}
+
+// reading from a slice consumes the first element of the slice
+impl<C: Clone> IntcodeInput<C> for &[C] {
+    fn read(&mut self) -> Option<C> {
+        let (first, rest) = self.split_first()?;
+        *self = rest;
+        Some(first.clone())
+    }
+}
+
+impl<C> IntcodeInput<C> for VecDeque<C> {
+    fn read(&mut self) -> Option<C> {
+        self.pop_front()
+    }
+}
+
+impl<C> IntcodeOutput<C> for Vec<C> {
+    fn write(&mut self, value: C) {
+        self.push(value);
+    }
+}
+
+impl<C> IntcodeOutput<C> for VecDeque<C> {
+    fn write(&mut self, value: C) {
+        self.push_back(value);
+    }
+}
+
+// Limits for a single call of `run_limited`. When a limit is reached, the machine stops in a
+// resumable state, so the next call continues with a fresh budget.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
+pub struct Limits {
+    // maximum number of instructions to execute, `ReturnStatus::OutOfFuel` when exceeded. The
+    // final halt instruction does not count.
+    pub max_instructions: Option<u64>,
+    // maximum number of output values to write, `ReturnStatus::OutputFull` when reached
+    pub max_output: Option<usize>,
+}
+
+impl Limits {
+    pub fn unlimited() -> Limits {
+        Limits::default()
+    }
+
+    pub fn instructions(max_instructions: u64) -> Limits {
+        Limits {
+            max_instructions: Some(max_instructions),
+            max_output: None,
+        }
+    }
+}
+
+// Run the program in `state` until it halts or blocks on input. Unlike `run_program`, this
+// works on the state in place, so it can be resumed without cloning the state. In case of an
+// error, the state is left at the failing instruction.
+pub fn run<M, I, O>(
+    state: &mut State<M>,
+    input: &mut I,
+    output: &mut O,
+) -> Result<ReturnStatus, IntcodeError>
+where
+    M: Memory,
+    I: IntcodeInput<M::Cell> + ?Sized,
+    O: IntcodeOutput<M::Cell> + ?Sized,
+{
+    run_limited(state, input, output, &Limits::unlimited())
+}
+
+// Like `run`, but stops when one of the limits is reached.
+pub fn run_limited<M, I, O>(
+    state: &mut State<M>,
+    input: &mut I,
+    output: &mut O,
+    limits: &Limits,
+) -> Result<ReturnStatus, IntcodeError>
+where
+    M: Memory,
+    I: IntcodeInput<M::Cell> + ?Sized,
+    O: IntcodeOutput<M::Cell> + ?Sized,
+{
+    run_traced(state, input, output, limits, &mut ())
+}
+
+// Like `run_limited`, but every executed instruction is passed to `sink`.
+pub fn run_traced<M, I, O, T>(
+    state: &mut State<M>,
+    input: &mut I,
+    output: &mut O,
+    limits: &Limits,
+    sink: &mut T,
+) -> Result<ReturnStatus, IntcodeError>
+where
+    M: Memory,
+    I: IntcodeInput<M::Cell> + ?Sized,
+    O: IntcodeOutput<M::Cell> + ?Sized,
+    T: TraceSink<M::Cell> + ?Sized,
+{
+    run_steps(state, output, limits, at_halt, |state| {
+        if !sink.enabled() {
+            return Ok(state.step_with(input)?.event);
+        }
+        let entry = state.step_traced(input)?;
+        if entry.step.event != StepEvent::InputRequired {
+            sink.record(&entry);
+        }
+        Ok(entry.step.event)
+    })
+}
+
+// the next instruction is a halt instruction
+pub(crate) fn at_halt<M: Memory>(state: &State<M>) -> bool {
+    state.mem.read(state.ip) == M::Cell::from_isize(99)
+}
+
+// The loop behind all run functions: executes instructions of `machine` with `step` until the
+// program halts, blocks on input or a limit is reached. `at_halt` tells if the next instruction
+// is a halt instruction, which is executed even without fuel.
+pub(crate) fn run_steps<S, C, O, H, F>(
+    machine: &mut S,
+    output: &mut O,
+    limits: &Limits,
+    at_halt: H,
+    mut step: F,
+) -> Result<ReturnStatus, IntcodeError>
+where
+    O: IntcodeOutput<C> + ?Sized,
+    H: Fn(&S) -> bool,
+    F: FnMut(&mut S) -> Result<StepEvent<C>, IntcodeError>,
+{
+    let mut executed: u64 = 0;
+    let mut written: usize = 0;
+    loop {
+        if limits.max_instructions.map(|max| executed >= max) == Some(true) && !at_halt(machine) {
+            return Ok(ReturnStatus::OutOfFuel);
+        }
+        match step(machine)? {
+            StepEvent::Continue | StepEvent::Input(_) => (),
+            StepEvent::Output(value) => {
+                output.write(value);
+                written += 1;
+                if limits.max_output.map(|max| written >= max) == Some(true) {
+                    return Ok(ReturnStatus::OutputFull);
+                }
+            }
+            // No input to read. Return the control flow to the caller
+            StepEvent::InputRequired => return Ok(ReturnStatus::Wait),
+            StepEvent::Halt => return Ok(ReturnStatus::Halt),
+        }
+        executed += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn run_can_be_resumed_on_the_same_state() {
+        // given
+        // adds up two inputs at a time and outputs the sum, until the sum is 0
+        let mut state = State::new(vec![
+            3, 15, 3, 16, 1, 15, 16, 17, 4, 17, 1005, 17, 0, 99, 0, 0, 0, 0,
+        ]);
+        let mut input: VecDeque<isize> = VecDeque::new();
+        let mut output: Vec<isize> = Vec::new();
+
+        // when
+        input.push_back(1);
+        let status1 = run(&mut state, &mut input, &mut output).expect("Expected valid run");
+        input.push_back(2);
+        input.push_back(3);
+        input.push_back(4);
+        let status2 = run(&mut state, &mut input, &mut output).expect("Expected valid run");
+        input.push_back(-4);
+        input.push_back(4);
+        let status3 = run(&mut state, &mut input, &mut output).expect("Expected valid run");
+
+        // then
+        assert_eq!(status1, ReturnStatus::Wait);
+        assert_eq!(status2, ReturnStatus::Wait);
+        assert_eq!(status3, ReturnStatus::Halt);
+        assert_eq!(output, vec![3, 7, 0]);
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn run_only_consumes_needed_input() {
+        // given
+        let mut state = State::new(vec![3, 5, 4, 5, 99, 0]);
+        let mut input: &[isize] = &[1, 2, 3];
+        let mut output: Vec<isize> = Vec::new();
+
+        // when
+        let status = run(&mut state, &mut input, &mut output).expect("Expected valid run");
+
+        // then
+        assert_eq!(status, ReturnStatus::Halt);
+        assert_eq!(output, vec![1]);
+        assert_eq!(input, &[2, 3]);
+    }
+
+    #[test]
+    fn run_limited_stops_endless_loop_and_can_be_resumed() {
+        // given
+        // counts upwards forever, printing every number
+        let mut state = State::new(vec![4, 9, 101, 1, 9, 9, 1105, 1, 0, 0]);
+        let mut output: Vec<isize> = Vec::new();
+        let limits = Limits::instructions(9);
+
+        // when
+        let status1 =
+            run_limited(&mut state, &mut None, &mut output, &limits).expect("Expected valid run");
+        let status2 =
+            run_limited(&mut state, &mut None, &mut output, &limits).expect("Expected valid run");
+
+        // then
+        assert_eq!(status1, ReturnStatus::OutOfFuel);
+        assert_eq!(status2, ReturnStatus::OutOfFuel);
+        assert_eq!(output, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(state.ip, 0);
+    }
+
+    #[test]
+    fn run_limited_stops_at_output_limit() {
+        // given
+        let mut state = State::new(vec![104, 1, 104, 2, 104, 3, 99]);
+        let mut output: Vec<isize> = Vec::new();
+        let limits = Limits {
+            max_instructions: None,
+            max_output: Some(2),
+        };
+
+        // when
+        let status1 =
+            run_limited(&mut state, &mut None, &mut output, &limits).expect("Expected valid run");
+        let status2 =
+            run_limited(&mut state, &mut None, &mut output, &limits).expect("Expected valid run");
+
+        // then
+        assert_eq!(status1, ReturnStatus::OutputFull);
+        assert_eq!(status2, ReturnStatus::Halt);
+        assert_eq!(output, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn run_limited_halts_within_budget() {
+        // given
+        let mut state = State::new(vec![1101, 1, 1, 5, 99, 0]);
+        let mut output: Vec<isize> = Vec::new();
+
+        // when
+        let status = run_limited(&mut state, &mut None, &mut output, &Limits::instructions(1))
+            .expect("Expected valid run");
+
+        // then
+        assert_eq!(status, ReturnStatus::Halt);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::{DenseMemory, IntcodeError, IntcodeInput, Memory, State, StepEvent, TraceEntry};
+use std::collections::VecDeque;
+
+// Everything needed to undo a single instruction
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+struct Record<C> {
+    ip: usize,
+    rel_base: isize,
+    // memory length before the instruction
+    len: usize,
+    // address and previous value of the written cell
+    write: Option<(usize, C)>,
+    event: StepEvent<C>,
+}
+
+// A full copy of the state and the records of the instructions executed after it
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+struct Segment<M: Memory> {
+    // position of the checkpoint
+    start: u64,
+    checkpoint: State<M>,
+    records: Vec<Record<M::Cell>>,
+}
+
+// An undo journal for reverse execution. Instructions executed with `Journal::step` can be
+// undone one by one or up to an earlier I/O event.
+//
+// The history is split into segments of `checkpoint_interval` instructions, each starting with
+// a full copy of the state. Only the last `max_checkpoints` segments are kept, so memory usage
+// is bounded and instructions before the oldest checkpoint can not be undone anymore.
+// Checkpoints also make long rewinds cheap: `rewind_to` restores the nearest checkpoint and
+// executes the remaining instructions again instead of undoing them one by one.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Journal<M: Memory = DenseMemory> {
+    segments: VecDeque<Segment<M>>,
+    checkpoint_interval: usize,
+    max_checkpoints: usize,
+}
+
+impl<M: Memory + Clone> Journal<M> {
+    // `checkpoint_interval` and `max_checkpoints` must be at least 1
+    pub fn new(checkpoint_interval: usize, max_checkpoints: usize) -> Journal<M> {
+        Journal {
+            segments: VecDeque::new(),
+            checkpoint_interval: checkpoint_interval.max(1),
+            max_checkpoints: max_checkpoints.max(1),
+        }
+    }
+
+    // Forget all recorded instructions, e.g. because the state was replaced. The position
+    // starts at 0 again.
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    // number of instructions executed with this journal, minus the ones that were undone
+    pub fn position(&self) -> u64 {
+        self.segments
+            .back()
+            .map(|segment| segment.start + segment.records.len() as u64)
+            .unwrap_or(0)
+    }
+
+    // the earliest position that can still be restored
+    pub fn earliest(&self) -> u64 {
+        self.segments
+            .front()
+            .map(|segment| segment.start)
+            .unwrap_or(0)
+    }
+
+    // Execute a single instruction like `State::step_traced` and record how to undo it.
+    // Instructions that block on input or halt change nothing and are not recorded.
+    pub fn step<I>(
+        &mut self,
+        state: &mut State<M>,
+        input: &mut I,
+    ) -> Result<TraceEntry<M::Cell>, IntcodeError>
+    where
+        I: IntcodeInput<M::Cell> + ?Sized,
+    {
+        let needs_checkpoint = self
+            .segments
+            .back()
+            .map(|segment| segment.records.len() >= self.checkpoint_interval)
+            .unwrap_or(true);
+        let checkpoint = if needs_checkpoint {
+            Some(state.clone())
+        } else {
+            None
+        };
+        let ip = state.ip;
+        let rel_base = state.rel_base;
+        let len = state.mem.len();
+        let write = state
+            .write_address()
+            .map(|address| (address, state.mem.read(address)));
+
+        let entry = state.step_traced(input)?;
+        if let StepEvent::InputRequired | StepEvent::Halt = entry.step.event {
+            return Ok(entry);
+        }
+        if let Some(checkpoint) = checkpoint {
+            let start = self.position();
+            self.segments.push_back(Segment {
+                start,
+                checkpoint,
+                records: Vec::with_capacity(self.checkpoint_interval),
+            });
+            if self.segments.len() > self.max_checkpoints {
+                self.segments.pop_front();
+            }
+        }
+        let record = Record {
+            ip,
+            rel_base,
+            len,
+            write: entry.step.write.as_ref().and(write),
+            event: entry.step.event.clone(),
+        };
+        if let Some(segment) = self.segments.back_mut() {
+            segment.records.push(record);
+        }
+        Ok(entry)
+    }
+
+    // Undo the last recorded instruction. Returns its event, so the caller can undo the I/O
+    // (put input values back, remove output values). `None` if there is nothing left to undo.
+    pub fn step_back(&mut self, state: &mut State<M>) -> Option<StepEvent<M::Cell>> {
+        self.drop_empty_segments();
+        let record = self.segments.back_mut()?.records.pop()?;
+        if let Some((address, value)) = record.write {
+            // the cell was written before, so writing it again can not fail
+            let _ = state.mem.write(address, value);
+        }
+        state.mem.truncate(record.len);
+        state.ip = record.ip;
+        state.rel_base = record.rel_base;
+        Some(record.event)
+    }
+
+    // Undo instructions until an input or output instruction was undone. Returns the number of
+    // undone instructions and the I/O event (`None` if the history ran out before).
+    pub fn rewind_to_io(&mut self, state: &mut State<M>) -> (u64, Option<StepEvent<M::Cell>>) {
+        let mut count = 0;
+        while let Some(event) = self.step_back(state) {
+            count += 1;
+            if let StepEvent::Input(_) | StepEvent::Output(_) = event {
+                return (count, Some(event));
+            }
+        }
+        (count, None)
+    }
+
+    // Go back to `position` (clamped to `earliest()`). Returns the I/O events of the undone
+    // instructions, latest first.
+    pub fn rewind_to(&mut self, state: &mut State<M>, position: u64) -> Vec<StepEvent<M::Cell>> {
+        let position = position.max(self.earliest());
+        let mut undone = Vec::new();
+        while self.position() > position {
+            self.drop_empty_segments();
+            let segment = match self.segments.back_mut() {
+                Some(segment) => segment,
+                None => break,
+            };
+            let keep = position.saturating_sub(segment.start) as usize;
+            let io_events = segment.records[keep..]
+                .iter()
+                .rev()
+                .map(|record| &record.event)
+                .filter(|event| matches!(event, StepEvent::Input(_) | StepEvent::Output(_)))
+                .cloned();
+            undone.extend(io_events);
+            let count = segment.records.len() - keep;
+            if keep < count {
+                // closer to the checkpoint: restore it and execute the kept instructions again
+                let mut restored = segment.checkpoint.clone();
+                for record in &segment.records[..keep] {
+                    let mut input = match &record.event {
+                        StepEvent::Input(value) => Some(value.clone()),
+                        _ => None,
+                    };
+                    // this already worked once with the same input
+                    let _ = restored.step_with(&mut input);
+                }
+                *state = restored;
+                segment.records.truncate(keep);
+            } else {
+                for _ in 0..count {
+                    self.step_back(state);
+                }
+            }
+        }
+        undone
+    }
+
+    // A segment without records is kept as long as it is the only one: its checkpoint is the
+    // current state and its start is the current position.
+    fn drop_empty_segments(&mut self) {
+        while self.segments.len() > 1
+            && self.segments.back().map(|s| s.records.is_empty()) == Some(true)
+        {
+            self.segments.pop_back();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // reads a value, stores it far behind the program, moves the relative base and prints the
+    // value multiplied by 2, forever
+    const PROGRAM: &[isize] = &[3, 100, 109, 1, 1002, 100, 2, 101, 4, 101, 1105, 1, 0];
+
+    fn run_steps(state: &mut State, journal: &mut Journal, input: &mut VecDeque<isize>, n: usize) {
+        for _ in 0..n {
+            journal.step(state, input).expect("Expected valid step");
+        }
+    }
+
+    #[test]
+    fn step_back_restores_previous_state() {
+        // given
+        let mut state = State::new(PROGRAM.to_vec());
+        let mut journal = Journal::new(100, 1);
+        let mut input: VecDeque<isize> = vec![5].into();
+        let initial = state.clone();
+
+        // when
+        run_steps(&mut state, &mut journal, &mut input, 5);
+        let after_five = state.clone();
+        let blocked = journal
+            .step(&mut state, &mut input)
+            .expect("Expected valid step");
+        let events: Vec<Option<StepEvent>> =
+            (0..6).map(|_| journal.step_back(&mut state)).collect();
+
+        // then
+        assert_eq!(after_five.mem.len(), 102);
+        assert_eq!(after_five.rel_base, 1);
+        assert_eq!(blocked.step.event, StepEvent::InputRequired);
+        assert_eq!(
+            events,
+            vec![
+                Some(StepEvent::Continue),
+                Some(StepEvent::Output(10)),
+                Some(StepEvent::Continue),
+                Some(StepEvent::Continue),
+                Some(StepEvent::Input(5)),
+                None
+            ]
+        );
+        assert_eq!(state, initial);
+        assert_eq!(journal.position(), 0);
+    }
+
+    #[test]
+    fn rewind_to_io_stops_at_last_io_event() {
+        // given
+        let mut state = State::new(PROGRAM.to_vec());
+        let mut journal = Journal::new(100, 1);
+        let mut input: VecDeque<isize> = vec![5, 6].into();
+
+        // when
+        run_steps(&mut state, &mut journal, &mut input, 7);
+        let result = journal.rewind_to_io(&mut state);
+
+        // then
+        assert_eq!(result, (2, Some(StepEvent::Input(6))));
+        assert_eq!(state.ip, 0);
+        assert_eq!(journal.position(), 5);
+    }
+
+    #[test]
+    fn rewind_to_matches_fresh_execution_and_respects_memory_bound() {
+        // given
+        let mut state = State::new(PROGRAM.to_vec());
+        let mut journal = Journal::new(4, 3);
+        let mut input: VecDeque<isize> = (1..=10).collect();
+        let fresh_state = |steps: usize| {
+            let mut state = State::new(PROGRAM.to_vec());
+            let mut input: VecDeque<isize> = (1..=10).collect();
+            run_steps(&mut state, &mut Journal::new(100, 1), &mut input, steps);
+            state
+        };
+
+        // when
+        run_steps(&mut state, &mut journal, &mut input, 30);
+        let earliest = journal.earliest();
+        let undone = journal.rewind_to(&mut state, 21);
+        let state_at_21 = state.clone();
+        let undone_to_earliest = journal.rewind_to(&mut state, 0);
+
+        // then
+        assert_eq!(earliest, 20);
+        assert_eq!(
+            undone,
+            vec![
+                StepEvent::Output(12),
+                StepEvent::Input(6),
+                StepEvent::Output(10),
+            ]
+        );
+        assert_eq!(state_at_21, fresh_state(21));
+        assert_eq!(undone_to_earliest, vec![StepEvent::Input(5)]);
+        assert_eq!(journal.position(), 20);
+        assert_eq!(state, fresh_state(20));
+    }
+}
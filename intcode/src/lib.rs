@@ -1,261 +1,258 @@
-pub fn parse(input: &str) -> Result<Vec<isize>, String> {
+mod analysis;
+mod ascii;
+mod asm;
+mod async_io;
+mod bigint;
+mod cell;
+mod codec;
+mod coverage;
+mod debugger;
+mod disasm;
+mod error;
+mod fast;
+mod fuzz;
+mod guard;
+mod instruction;
+mod io;
+mod journal;
+mod memory;
+mod network;
+mod profile;
+mod snapshot;
+mod step;
+mod threaded;
+mod trace;
+mod transcript;
+
+pub use analysis::{analyze, Analysis, Block, Issue, IssueKind, Severity};
+pub use ascii::{decode_ascii, encode_ascii, AsciiError, AsciiMachine, AsciiOutput};
+pub use asm::{assemble, to_intcode_string, AsmError};
+pub use async_io::{channel, run_async, AsyncInput, AsyncOutput, Receiver, Sender};
+pub use bigint::BigInt;
+pub use cell::Cell;
+pub use codec::DecodeError;
+pub use coverage::Coverage;
+pub use debugger::{Debugger, Stop};
+pub use disasm::{disassemble, listing, Line};
+pub use error::{IntcodeError, RunError};
+pub use fast::{run_program_fast, FastMachine};
+pub use fuzz::{check_program, fuzz, fuzz_case, FuzzConfig, FuzzFailure};
+pub use guard::{run_guarded, Access, CodeGuard, CodeWrite, CodeWritePolicy};
+pub use instruction::{Instruction, Mode, Opcode};
+pub use io::{run, run_limited, run_traced, IntcodeInput, IntcodeOutput, Limits};
+pub use journal::Journal;
+pub use memory::{DenseMemory, Memory, PagedMemory, DEFAULT_MAX_ADDRESS, PAGE_SIZE};
+pub use network::{
+    Dropper, Endpoint, LogEntry, Logger, Nat, Network, NetworkError, NetworkReport, NetworkStop,
+    Packet, Route, Schedule, SpecialNode,
+};
+pub use profile::{BranchCount, HotLoop, Profiler};
+pub use snapshot::Snapshot;
+pub use step::{Step, StepEvent};
+pub use threaded::{Cluster, ClusterReceiver, ClusterSender, InputPolicy};
+pub use trace::{
+    read_binary_trace, BinaryTraceSink, RingBufferSink, TextTraceSink, TraceEntry, TraceSink,
+};
+pub use transcript::{replay, Divergence, Recorder, Transcript, TranscriptEvent};
+
+pub fn parse(input: &str) -> Result<Vec<isize>, IntcodeError> {
+    parse_cells(input)
+}
+
+// like `parse`, but for any cell type, e.g. `parse_cells::<i64>(input)`
+pub fn parse_cells<C: Cell>(input: &str) -> Result<Vec<C>, IntcodeError> {
     input
         .split(',')
         .map(|s| s.trim())
-        .filter(|s| !s.is_empty())
-        .map(|s| s.parse::<isize>().map_err(|e| e.to_string()))
+        .enumerate()
+        .filter(|(_, s)| !s.is_empty())
+        .map(|(index, s)| {
+            C::from_token(s).map_err(|message| IntcodeError::Parse {
+                index,
+                token: s.to_owned(),
+                message,
+            })
+        })
         .collect()
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
-pub struct State {
-    pub mem: Vec<isize>,
+pub struct State<M = DenseMemory> {
+    pub mem: M,
     pub ip: usize,
     pub rel_base: isize,
 }
 
-impl State {
-    pub fn new(mem: Vec<isize>) -> State {
+impl<C: Cell> State<DenseMemory<C>> {
+    pub fn new(mem: Vec<C>) -> State<DenseMemory<C>> {
+        State::with_memory(DenseMemory::new(mem))
+    }
+}
+
+impl<C: Cell> State<PagedMemory<C>> {
+    // A state that can be forked cheaply, see `State::fork`
+    pub fn new_paged(program: &[C]) -> State<PagedMemory<C>> {
+        State::with_memory(PagedMemory::new(program))
+    }
+}
+
+impl<M: Memory> State<M> {
+    pub fn with_memory(mem: M) -> State<M> {
         State {
             mem,
             ip: 0,
             rel_base: 0,
         }
     }
+
+    // An independent copy of the machine, e.g. to branch a search at a decision point. With
+    // `PagedMemory`, the fork shares all pages with the original until one of them writes to a
+    // page, so forking costs one pointer per page instead of a copy of the whole memory.
+    pub fn fork(&self) -> State<M>
+    where
+        M: Clone,
+    {
+        self.clone()
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
 pub enum ReturnStatus {
     Halt,
     Wait,
+    // the instruction limit of `run_limited` was reached
+    OutOfFuel,
+    // the output limit of `run_limited` was reached
+    OutputFull,
 }
 
 // Return values: current state, return status and output
-pub fn run_program(
-    state: State,
-    input: &[isize],
-) -> Result<(State, ReturnStatus, Vec<isize>), String> {
-    let State {
-        mut mem,
-        mut ip,
-        mut rel_base,
-    } = state;
-    let mut output: Vec<isize> = Vec::new();
-    let mut in_iter = input.iter();
-    if mem.len() <= ip {
-        return Err("Invalid instruction pointer".to_owned());
-    }
-    while mem[ip] != 99 {
-        match mem[ip] % 100 {
-            // addition
-            1 => {
-                let (v1, v2, dest) = get_binary_op_operands(ip, &mem, rel_base)?;
-                write_value_at(dest, v1 + v2, &mut mem);
-                ip += 4;
-            }
-            // multiplication
-            2 => {
-                let (v1, v2, dest) = get_binary_op_operands(ip, &mem, rel_base)?;
-                write_value_at(dest, v1 * v2, &mut mem);
-                ip += 4;
-            }
-            // read
-            3 => {
-                let dest = get_input_dest(ip, &mem, rel_base)?;
-                if let Some(value) = in_iter.next() {
-                    write_value_at(dest, *value, &mut mem);
-                    ip += 2;
-                } else {
-                    // No input to read. Return the control flow to the caller
-                    return Ok((State { mem, ip, rel_base }, ReturnStatus::Wait, output));
-                }
-            }
-            // write
-            4 => {
-                output.push(get_single_operand(ip, &mem, rel_base)?);
-                ip += 2;
-            }
-            // jump not zero
-            5 => {
-                let (condition, dest) = get_two_operands(ip, &mem, rel_base)?;
-                ip = if condition != 0 {
-                    get_valid_address(dest, mem.len(), 0, 0)?
-                } else {
-                    ip + 3
-                }
-            }
-            // jump zero
-            6 => {
-                let (condition, dest) = get_two_operands(ip, &mem, rel_base)?;
-                ip = if condition == 0 {
-                    get_valid_address(dest, mem.len(), 0, 0)?
-                } else {
-                    ip + 3
-                }
-            }
-            // less than
-            7 => {
-                let (v1, v2, dest) = get_binary_op_operands(ip, &mem, rel_base)?;
-                let value = if v1 < v2 { 1 } else { 0 };
-                write_value_at(dest, value, &mut mem);
-                ip += 4;
-            }
-            // equals
-            8 => {
-                let (v1, v2, dest) = get_binary_op_operands(ip, &mem, rel_base)?;
-                let value = if v1 == v2 { 1 } else { 0 };
-                write_value_at(dest, value, &mut mem);
-                ip += 4;
-            }
-            // adjust relative base
-            9 => {
-                rel_base += get_single_operand(ip, &mem, rel_base)?;
-                ip += 2;
-            }
-            _ => {
-                return Err(format!("Unknown opcode {}", mem[ip]));
-            }
-        };
-        if ip >= mem.len() {
-            return Err("Program did not halt".to_owned());
-        }
-    }
-    Ok((State { mem, ip, rel_base }, ReturnStatus::Halt, output))
-}
-
-fn get_input_dest(ip: usize, mem: &[isize], rel_base: isize) -> Result<usize, String> {
-    if ip + 1 >= mem.len() {
-        return Err(format!(
-            "Not enough operands for ip {} and mem.len() {}",
-            ip,
-            mem.len()
-        ));
-    }
-    get_valid_address(mem[ip + 1], mem.len(), (mem[ip] / 100) % 10, rel_base)
-}
-
-fn get_single_operand(ip: usize, mem: &[isize], rel_base: isize) -> Result<isize, String> {
-    if ip + 1 >= mem.len() {
-        return Err(format!(
-            "Not enough operands for ip {} and mem.len() {}",
-            ip,
-            mem.len()
-        ));
+pub type RunResult<M = DenseMemory> =
+    Result<(State<M>, ReturnStatus, Vec<<M as Memory>::Cell>), RunError<M>>;
+
+// In case of an error, the state at the point of failure is returned along with the error.
+pub fn run_program<M: Memory>(mut state: State<M>, input: &[M::Cell]) -> RunResult<M> {
+    let mut output: Vec<M::Cell> = Vec::new();
+    match run(&mut state, &mut &input[..], &mut output) {
+        Ok(status) => Ok((state, status, output)),
+        Err(error) => Err(RunError {
+            error,
+            state,
+            output,
+        }),
     }
-    get_value(mem[ip + 1], (mem[ip] / 100) % 10, mem, rel_base)
 }
 
-fn get_two_operands(ip: usize, mem: &[isize], rel_base: isize) -> Result<(isize, isize), String> {
-    if ip + 2 >= mem.len() {
-        return Err(format!(
-            "Not enough operands for pc {} and mem.len() {}",
-            ip,
-            mem.len()
-        ));
+fn check_operand_count<M: Memory>(ip: usize, mem: &M, count: usize) -> Result<(), IntcodeError> {
+    if ip + count >= mem.len() {
+        Err(IntcodeError::MissingOperands {
+            address: ip,
+            mem_size: mem.len(),
+        })
+    } else {
+        Ok(())
     }
-    let v1 = get_value(mem[ip + 1], (mem[ip] / 100) % 10, mem, rel_base)?;
-    let v2 = get_value(mem[ip + 2], (mem[ip] / 1000) % 10, mem, rel_base)?;
-
-    Ok((v1, v2))
-}
-
-fn get_binary_op_operands(
-    ip: usize,
-    mem: &[isize],
-    rel_base: isize,
-) -> Result<(isize, isize, usize), String> {
-    if ip + 3 >= mem.len() {
-        return Err(format!(
-            "Not enough operands for pc {} and mem.len() {}",
-            ip,
-            mem.len()
-        ));
-    }
-    let v1 = get_value(mem[ip + 1], (mem[ip] / 100) % 10, mem, rel_base)?;
-    let v2 = get_value(mem[ip + 2], (mem[ip] / 1000) % 10, mem, rel_base)?;
-    let dest = get_valid_address(mem[ip + 3], mem.len(), (mem[ip] / 10000) % 10, rel_base)?;
-    Ok((v1, v2, dest))
 }
 
 fn get_valid_address(
     raw_address: isize,
-    memsize: usize,
-    mode: isize,
+    mode: Mode,
     rel_base: isize,
-) -> Result<usize, String> {
-    let calculated_address = if mode == 2 {
-        raw_address + rel_base
+) -> Result<usize, IntcodeError> {
+    let calculated_address = if mode == Mode::Relative {
+        raw_address
+            .checked_add(rel_base)
+            .ok_or(IntcodeError::ValueOutOfRange {
+                value: format!("{} + {}", raw_address, rel_base),
+            })?
     } else {
         raw_address
     };
     if calculated_address < 0 {
-        Err(format!(
-            "memory index {} is out of bounds (memsize: {})",
-            calculated_address, memsize
-        ))
+        Err(IntcodeError::NegativeAddress {
+            address: calculated_address,
+        })
     } else {
         Ok(calculated_address as usize)
     }
 }
 
-fn get_value(
-    raw_value: isize,
-    mode: isize,
-    mem: &[isize],
+// convert a cell that is used as address, relative base offset or jump target
+fn to_isize<C: Cell>(value: &C) -> Result<isize, IntcodeError> {
+    value
+        .to_isize()
+        .ok_or_else(|| IntcodeError::ValueOutOfRange {
+            value: value.to_string(),
+        })
+}
+
+fn get_value<M: Memory>(
+    raw_value: M::Cell,
+    mode: Mode,
+    mem: &M,
     rel_base: isize,
-) -> Result<isize, String> {
-    if mode == 1 {
-        Ok(raw_value)
-    } else if mode == 0 {
-        get_value_at(raw_value, mem)
-    } else if mode == 2 {
-        get_value_at(raw_value + rel_base, mem)
-    } else {
-        Err(format!("Unknown mode: {}", mode))
+) -> Result<M::Cell, IntcodeError> {
+    match mode {
+        Mode::Immediate => Ok(raw_value),
+        Mode::Position => get_value_at(to_isize(&raw_value)?, mem),
+        Mode::Relative => Ok(mem.read(get_valid_address(
+            to_isize(&raw_value)?,
+            Mode::Relative,
+            rel_base,
+        )?)),
     }
 }
 
-fn get_value_at(raw_address: isize, mem: &[isize]) -> Result<isize, String> {
-    Ok(mem
-        .get(get_valid_address(raw_address, mem.len(), 0, 0)?)
-        .cloned()
-        .unwrap_or(0))
+fn get_value_at<M: Memory>(raw_address: isize, mem: &M) -> Result<M::Cell, IntcodeError> {
+    Ok(mem.read(get_valid_address(raw_address, Mode::Position, 0)?))
 }
 
-fn extend_mem(length: usize, mem: &mut Vec<isize>) {
+fn extend_mem<C: Cell>(length: usize, mem: &mut Vec<C>) {
     if mem.len() < length {
-        mem.resize(length, 0);
+        mem.resize(length, C::zero());
     }
 }
 
-fn write_value_at(address: usize, value: isize, mem: &mut Vec<isize>) {
+fn write_value_at<C: Cell>(address: usize, value: C, mem: &mut Vec<C>) {
     extend_mem(address + 1, mem);
     mem[address] = value;
 }
 
+// programs used by the tests of several modules
+#[cfg(test)]
+mod test_programs {
+    // counts down from the input, printing every number
+    pub(crate) const COUNTDOWN: &[isize] = &[3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0];
+
+    // outputs 0, 1 and 2 by incrementing the parameter of its own output instruction
+    pub(crate) const COUNTER: &[isize] =
+        &[104, 0, 1001, 1, 1, 1, 1007, 1, 3, 14, 1005, 14, 0, 99, 0];
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::num::Wrapping;
 
     #[test]
     fn test_get_valid_address() {
         // position mode
-        assert_eq!(get_valid_address(42, 43, 0, 1000), Ok(42));
-        assert_eq!(get_valid_address(0, 43, 0, 1000), Ok(0));
-        assert_eq!(get_valid_address(43, 43, 0, 1000), Ok(43));
+        assert_eq!(get_valid_address(42, Mode::Position, 1000), Ok(42));
+        assert_eq!(get_valid_address(0, Mode::Position, 1000), Ok(0));
+        assert_eq!(get_valid_address(43, Mode::Position, 1000), Ok(43));
 
-        assert!(get_valid_address(-1, 43, 0, 1000).is_err());
+        assert!(get_valid_address(-1, Mode::Position, 1000).is_err());
 
         // relative mode
-        assert_eq!(get_valid_address(42, 43, 2, 1000), Ok(1042));
+        assert_eq!(get_valid_address(42, Mode::Relative, 1000), Ok(1042));
 
-        assert!(get_valid_address(-1001, 43, 2, 1000).is_err());
+        assert!(get_valid_address(-1001, Mode::Relative, 1000).is_err());
     }
 
     #[test]
     fn get_value_at_works_for_valid_adress() {
         // given
-        let mem = &[1, 2, 3];
+        let mem = &DenseMemory::<isize>::new(vec![1, 2, 3]);
         let address = 2;
 
         // when
@@ -268,7 +265,7 @@ mod test {
     #[test]
     fn get_value_at_works_for_too_high_address() {
         // given
-        let mem = &[1, 2, 3];
+        let mem = &DenseMemory::<isize>::new(vec![1, 2, 3]);
         let address = 42;
 
         // when
@@ -281,7 +278,7 @@ mod test {
     #[test]
     fn get_value_at_fails_for_invalid_address() {
         // given
-        let mem = &[1, 2, 3];
+        let mem = &DenseMemory::<isize>::new(vec![1, 2, 3]);
         let address = -1;
 
         // when
@@ -294,9 +291,9 @@ mod test {
     #[test]
     fn get_value_works_for_position_mode() {
         // given
-        let mem = &[10, 20, 30];
+        let mem = &DenseMemory::<isize>::new(vec![10, 20, 30]);
         let raw_value = 2;
-        let mode = 0;
+        let mode = Mode::Position;
 
         // when
         let result = get_value(raw_value, mode, mem, 0);
@@ -308,9 +305,9 @@ mod test {
     #[test]
     fn get_value_works_for_immediate_mode() {
         // given
-        let mem = &[10, 20, 30];
+        let mem = &DenseMemory::<isize>::new(vec![10, 20, 30]);
         let raw_value = 2;
-        let mode = 1;
+        let mode = Mode::Immediate;
 
         // when
         let result = get_value(raw_value, mode, mem, 0);
@@ -322,9 +319,9 @@ mod test {
     #[test]
     fn get_value_works_for_relative_mode() {
         // given
-        let mem = &[1, 2, 42];
+        let mem = &DenseMemory::<isize>::new(vec![1, 2, 42]);
         let raw_value = 1;
-        let mode = 2;
+        let mode = Mode::Relative;
         let rel_base = 1;
 
         // when
@@ -337,7 +334,7 @@ mod test {
     #[test]
     fn write_value_at_writes_value_for_in_bound_address() {
         // given
-        let mut mem = vec![1, 2, 3];
+        let mut mem: Vec<isize> = vec![1, 2, 3];
         let address = 1;
         let value = 42;
 
@@ -352,7 +349,7 @@ mod test {
     #[test]
     fn write_value_at_extends_memory_for_oob_address() {
         // given
-        let mut mem = vec![1, 2, 3];
+        let mut mem: Vec<isize> = vec![1, 2, 3];
         let address = 4;
         let value = 42;
 
@@ -372,13 +369,13 @@ mod test {
 
         // when
         let (state, status, output) = run_program(
-            State::new(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]),
+            State::<DenseMemory>::new(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]),
             input,
         )
         .expect("Expected successful run");
 
         // then
-        assert_eq!(state.mem.get(0), Some(&3500));
+        assert_eq!(state.mem.read(0), 3500);
         assert_eq!(status, ReturnStatus::Halt);
         assert!(output.is_empty());
     }
@@ -390,15 +387,20 @@ mod test {
 
         // when
         let (state, status, output) =
-            run_program(State::new(vec![1, 0, 0, 0, 99]), input).expect("Expected successful run");
+            run_program(State::<DenseMemory>::new(vec![1, 0, 0, 0, 99]), input)
+                .expect("Expected successful run");
 
         // then
-        assert_eq!(state.mem.get(0), Some(&2));
+        assert_eq!(state.mem.read(0), 2);
         assert_eq!(status, ReturnStatus::Halt);
         assert!(output.is_empty());
     }
 
     fn test_program(prog: Vec<isize>, input: &[isize], expected_output: &[isize]) {
+        test_program_with(prog, input, expected_output)
+    }
+
+    fn test_program_with<C: Cell>(prog: Vec<C>, input: &[C], expected_output: &[C]) {
         let (_, status, output) =
             run_program(State::new(prog), input).expect("Expected program to halt gracefully");
 
@@ -441,7 +443,7 @@ mod test {
     #[test]
     fn test_day7_copy_itself() {
         // given
-        let prog = vec![
+        let prog: Vec<isize> = vec![
             109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
         ];
 
@@ -456,7 +458,7 @@ mod test {
 
     #[test]
     fn test_day7_direct_large_number() {
-        test_program(
+        test_program_with::<i64>(
             vec![104, 1_125_899_906_842_624, 99],
             &[],
             &[1_125_899_906_842_624],
@@ -465,10 +467,241 @@ mod test {
 
     #[test]
     fn test_day7_calculate_large_number() {
-        test_program(
+        test_program_with::<i64>(
             vec![1102, 34_915_192, 34_915_192, 7, 4, 7, 99, 0],
             &[],
             &[1_219_070_632_396_864],
         );
     }
+
+    #[test]
+    fn parse_reports_index_of_invalid_token() {
+        // when
+        let result = parse("1,2, x3,4");
+
+        // then
+        match result {
+            Err(IntcodeError::Parse { index, token, .. }) => {
+                assert_eq!(index, 2);
+                assert_eq!(token, "x3");
+            }
+            _ => panic!("Expected parse error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn run_program_returns_state_at_unknown_opcode() {
+        // given
+        let prog: Vec<isize> = vec![104, 7, 1101, 1, 2, 7, 42, 0];
+
+        // when
+        let result = run_program(State::new(prog), &[]);
+
+        // then
+        let error = result.expect_err("Expected unknown opcode");
+        assert_eq!(
+            error.error,
+            IntcodeError::UnknownOpcode {
+                address: 6,
+                value: 42
+            }
+        );
+        assert_eq!(error.state.ip, 6);
+        assert_eq!(error.state.mem.read(7), 3);
+        assert_eq!(error.output, vec![7]);
+    }
+
+    #[test]
+    fn run_program_fails_for_invalid_mode() {
+        // when
+        let result = run_program(State::<DenseMemory>::new(vec![304, 0, 99]), &[]);
+
+        // then
+        assert_eq!(
+            result.map_err(|e| e.error),
+            Err(IntcodeError::InvalidMode {
+                address: 0,
+                mode: 3
+            })
+        );
+    }
+
+    #[test]
+    fn run_program_fails_for_negative_address() {
+        // when
+        let result = run_program(State::<DenseMemory>::new(vec![204, -1, 99]), &[]);
+
+        // then
+        assert_eq!(
+            result.map_err(|e| e.error),
+            Err(IntcodeError::NegativeAddress { address: -1 })
+        );
+    }
+
+    #[test]
+    fn run_program_fails_if_ip_leaves_memory() {
+        // when
+        let result = run_program(State::<DenseMemory>::new(vec![1105, 1, 42]), &[]);
+
+        // then
+        assert_eq!(
+            result.map_err(|e| e.error),
+            Err(IntcodeError::IpOutOfBounds {
+                ip: 42,
+                mem_size: 3
+            })
+        );
+    }
+
+    #[test]
+    fn run_program_fails_cleanly_for_write_to_huge_address() {
+        // given
+        let prog: Vec<isize> = vec![1101, 1, 2, 1 << 30, 4, 1 << 30, 99];
+
+        // when
+        let result = run_program(State::new(prog), &[]);
+
+        // then
+        let error = result.expect_err("Expected write to fail");
+        assert_eq!(
+            error.error,
+            IntcodeError::AddressOutOfRange {
+                address: 1 << 30,
+                max_address: DEFAULT_MAX_ADDRESS
+            }
+        );
+        assert_eq!(error.state.ip, 0);
+        assert_eq!(error.state.mem.len(), 7);
+    }
+
+    #[test]
+    fn run_program_works_with_paged_memory() {
+        // given
+        let prog: Vec<isize> = vec![1101, 1, 2, 1 << 30, 4, 1 << 30, 99];
+
+        // when
+        let (state, status, output) = run_program(State::with_memory(PagedMemory::new(&prog)), &[])
+            .expect("Expected successful run");
+
+        // then
+        assert_eq!(status, ReturnStatus::Halt);
+        assert_eq!(output, vec![3]);
+        assert_eq!(state.mem.page_count(), 2);
+    }
+
+    #[test]
+    fn run_program_fails_on_overflow_for_isize_cells() {
+        // when
+        let result = run_program(
+            State::<DenseMemory>::new(vec![104, 0, 1102, isize::MAX, 2, 0, 99]),
+            &[],
+        );
+
+        // then
+        assert_eq!(
+            result.map_err(|e| e.error),
+            Err(IntcodeError::Overflow { address: 2 })
+        );
+    }
+
+    #[test]
+    fn run_program_wraps_for_wrapping_cells() {
+        // given
+        let prog: Vec<Wrapping<isize>> = vec![1101, isize::MAX, 2, 7, 4, 7, 99, 0]
+            .into_iter()
+            .map(Wrapping)
+            .collect();
+
+        // when
+        let (_, status, output) =
+            run_program(State::new(prog), &[]).expect("Expected successful run");
+
+        // then
+        assert_eq!(status, ReturnStatus::Halt);
+        assert_eq!(output, vec![Wrapping(isize::MIN + 1)]);
+    }
+
+    #[test]
+    fn run_program_calculates_beyond_64_bit_with_big_int_cells() {
+        // given
+        let prog: Vec<BigInt> = vec![1102, 1 << 62, 1 << 62, 7, 4, 7, 99, 0]
+            .into_iter()
+            .map(BigInt::from)
+            .collect();
+        let input: Vec<BigInt> = vec![];
+
+        // when
+        let (_, status, output) =
+            run_program(State::new(prog), &input).expect("Expected successful run");
+
+        // then
+        assert_eq!(status, ReturnStatus::Halt);
+        assert_eq!(
+            output,
+            vec!["21267647932558653966460912964485513216"
+                .parse::<BigInt>()
+                .expect("Expected valid number")]
+        );
+    }
+
+    #[test]
+    fn parse_cells_works_for_all_cell_types() {
+        // given
+        let input = "1102,-170141183460469231731687303715884105728,1";
+
+        // when
+        let small = parse_cells::<i64>(input);
+        let large = parse_cells::<i128>(input).expect("Expected valid i128 program");
+        let big = parse_cells::<BigInt>(input).expect("Expected valid BigInt program");
+
+        // then
+        assert_eq!(
+            small.map_err(|e| match e {
+                IntcodeError::Parse { index, .. } => index,
+                _ => 0,
+            }),
+            Err(1)
+        );
+        assert_eq!(large, vec![1102, i128::MIN, 1]);
+        assert_eq!(big[1].to_string(), i128::MIN.to_string());
+    }
+
+    #[test]
+    fn run_program_works_for_i128_cells() {
+        // given
+        let prog = parse_cells::<i128>("1102,4611686018427387904,4,7,4,7,99,0")
+            .expect("Expected valid program");
+
+        // when
+        let (_, _, output) = run_program(State::new(prog), &[]).expect("Expected successful run");
+
+        // then
+        assert_eq!(output, vec![1 << 64]);
+    }
+
+    #[test]
+    fn forks_run_independently_and_share_unwritten_pages() {
+        // given
+        // reads a value, stores it behind the first page and outputs it doubled
+        let prog: Vec<isize> = vec![3, 2000, 1002, 2000, 2, 2000, 4, 2000, 99];
+        let mut base = State::new_paged(&prog);
+        let status = run(&mut base, &mut None, &mut Vec::new()).expect("Expected valid run");
+
+        // when
+        let outputs: Vec<isize> = (0..1000)
+            .map(|i| {
+                let mut fork = base.fork();
+                let mut output: Vec<isize> = Vec::new();
+                run(&mut fork, &mut Some(i), &mut output).expect("Expected valid run");
+                assert_eq!(fork.mem.shared_pages(&base.mem), 1);
+                output[0]
+            })
+            .collect();
+
+        // then
+        assert_eq!(status, ReturnStatus::Wait);
+        assert_eq!(outputs, (0..1000).map(|i| i * 2).collect::<Vec<isize>>());
+        assert_eq!(base.mem.len(), prog.len());
+        assert_eq!(base.mem.read(2000), 0);
+    }
 }
@@ -0,0 +1,413 @@
+use crate::{write_value_at, Cell, IntcodeError};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// Highest address a `DenseMemory` may write to by default (128 MiB of cells on 64 bit systems).
+// Programs that need more can use `DenseMemory::with_max_address` or a `PagedMemory`.
+pub const DEFAULT_MAX_ADDRESS: usize = (1 << 24) - 1;
+
+pub const PAGE_SIZE: usize = 1024;
+
+pub trait Memory {
+    type Cell: Cell;
+
+    // value at `address`. Cells that were never written are 0.
+    fn read(&self, address: usize) -> Self::Cell;
+
+    // Fails for addresses above `max_address()`, the memory stays unchanged in that case.
+    fn write(&mut self, address: usize, value: Self::Cell) -> Result<(), IntcodeError>;
+
+    // one more than the highest address that was part of the program or written to
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn max_address(&self) -> usize;
+
+    // Shrink the memory to `len` cells, cells at higher addresses become 0. Does nothing if the
+    // memory is not longer than `len`.
+    fn truncate(&mut self, len: usize);
+
+    fn check_address(&self, address: usize) -> Result<(), IntcodeError> {
+        if address > self.max_address() {
+            Err(IntcodeError::AddressOutOfRange {
+                address,
+                max_address: self.max_address(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn to_vec(&self) -> Vec<Self::Cell> {
+        (0..self.len()).map(|address| self.read(address)).collect()
+    }
+
+    // The memory as (start address, cells) blocks in ascending order. Cells outside of the
+    // blocks are 0. Used to save memory without allocating the unused parts.
+    fn blocks(&self) -> Vec<(usize, Vec<Self::Cell>)> {
+        vec![(0, self.to_vec())]
+    }
+
+    // Create memory of length `len` from blocks as returned by `blocks`. Blocks must not reach
+    // beyond `len`.
+    fn from_blocks(blocks: Vec<(usize, Vec<Self::Cell>)>, len: usize, max_address: usize) -> Self
+    where
+        Self: Sized;
+}
+
+// Memory as one contiguous vector that is resized when writing behind its end. Fast, but
+// writing to a high address allocates everything below it.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct DenseMemory<C = isize> {
+    cells: Vec<C>,
+    max_address: usize,
+}
+
+impl<C: Cell> DenseMemory<C> {
+    pub fn new(cells: Vec<C>) -> DenseMemory<C> {
+        DenseMemory::with_max_address(cells, DEFAULT_MAX_ADDRESS)
+    }
+
+    pub fn with_max_address(cells: Vec<C>, max_address: usize) -> DenseMemory<C> {
+        DenseMemory { cells, max_address }
+    }
+
+    pub fn as_slice(&self) -> &[C] {
+        &self.cells
+    }
+
+    pub fn into_vec(self) -> Vec<C> {
+        self.cells
+    }
+}
+
+impl<C: Cell> From<Vec<C>> for DenseMemory<C> {
+    fn from(cells: Vec<C>) -> DenseMemory<C> {
+        DenseMemory::new(cells)
+    }
+}
+
+impl<C: Cell> Memory for DenseMemory<C> {
+    type Cell = C;
+
+    fn read(&self, address: usize) -> C {
+        self.cells.get(address).cloned().unwrap_or_else(C::zero)
+    }
+
+    fn write(&mut self, address: usize, value: C) -> Result<(), IntcodeError> {
+        self.check_address(address)?;
+        write_value_at(address, value, &mut self.cells);
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn max_address(&self) -> usize {
+        self.max_address
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.cells.truncate(len);
+    }
+
+    fn to_vec(&self) -> Vec<C> {
+        self.cells.clone()
+    }
+
+    fn from_blocks(blocks: Vec<(usize, Vec<C>)>, len: usize, max_address: usize) -> Self {
+        // grows with the blocks, only the zeros behind the last block are added for `len`
+        let mut cells = Vec::new();
+        for (start, block) in blocks {
+            for (i, value) in block.into_iter().enumerate() {
+                write_value_at(start + i, value, &mut cells);
+            }
+        }
+        if cells.len() < len {
+            cells.resize(len, C::zero());
+        }
+        DenseMemory::with_max_address(cells, max_address)
+    }
+}
+
+// Memory split into pages of `PAGE_SIZE` cells. Pages are only allocated when they are written
+// to, so far apart addresses only cost one page each.
+//
+// Pages are shared between clones and only copied when one of the clones writes to them
+// (copy-on-write), so cloning the memory costs one pointer per page.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct PagedMemory<C = isize> {
+    pages: BTreeMap<usize, Arc<Vec<C>>>,
+    len: usize,
+    max_address: usize,
+}
+
+impl<C: Cell> PagedMemory<C> {
+    pub fn new(program: &[C]) -> PagedMemory<C> {
+        PagedMemory::with_max_address(program, isize::MAX as usize)
+    }
+
+    // `max_address` must be at least `program.len() - 1`. It is at most `usize::MAX - 1`, so
+    // that the length of the memory fits into a `usize`.
+    pub fn with_max_address(program: &[C], max_address: usize) -> PagedMemory<C> {
+        let mut memory = PagedMemory {
+            pages: BTreeMap::new(),
+            len: program.len(),
+            max_address: max_address.min(usize::MAX - 1),
+        };
+        for (i, chunk) in program.chunks(PAGE_SIZE).enumerate() {
+            memory.page_mut(i)[..chunk.len()].clone_from_slice(chunk);
+        }
+        memory
+    }
+
+    // number of allocated pages
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    // number of pages this memory shares with `other`, e.g. with the memory it was cloned from
+    pub fn shared_pages(&self, other: &PagedMemory<C>) -> usize {
+        self.pages
+            .iter()
+            .filter(|(index, page)| {
+                other
+                    .pages
+                    .get(index)
+                    .map(|other_page| Arc::ptr_eq(page, other_page))
+                    == Some(true)
+            })
+            .count()
+    }
+
+    // the page with the given index for writing, allocated or copied if necessary
+    fn page_mut(&mut self, index: usize) -> &mut [C] {
+        let page = self
+            .pages
+            .entry(index)
+            .or_insert_with(|| Arc::new(vec![C::zero(); PAGE_SIZE]));
+        Arc::make_mut(page).as_mut_slice()
+    }
+}
+
+impl<C: Cell> Memory for PagedMemory<C> {
+    type Cell = C;
+
+    fn read(&self, address: usize) -> C {
+        self.pages
+            .get(&(address / PAGE_SIZE))
+            .map(|page| page[address % PAGE_SIZE].clone())
+            .unwrap_or_else(C::zero)
+    }
+
+    fn write(&mut self, address: usize, value: C) -> Result<(), IntcodeError> {
+        self.check_address(address)?;
+        self.page_mut(address / PAGE_SIZE)[address % PAGE_SIZE] = value;
+        self.len = self.len.max(address + 1);
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn max_address(&self) -> usize {
+        self.max_address
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        let first_unused_page = len.div_ceil(PAGE_SIZE);
+        self.pages.split_off(&first_unused_page);
+        if let Some(page) = self.pages.get_mut(&(len / PAGE_SIZE)) {
+            for cell in Arc::make_mut(page)[len % PAGE_SIZE..].iter_mut() {
+                *cell = C::zero();
+            }
+        }
+        self.len = len;
+    }
+
+    fn blocks(&self) -> Vec<(usize, Vec<C>)> {
+        self.pages
+            .iter()
+            .map(|(index, page)| {
+                let start = index * PAGE_SIZE;
+                let end = PAGE_SIZE.min(self.len - start);
+                (start, page[..end].to_vec())
+            })
+            .collect()
+    }
+
+    fn from_blocks(blocks: Vec<(usize, Vec<C>)>, len: usize, max_address: usize) -> Self {
+        let mut memory = PagedMemory::with_max_address(&[], max_address);
+        for (start, block) in blocks {
+            for (i, value) in block.into_iter().enumerate() {
+                let address = start + i;
+                memory.page_mut(address / PAGE_SIZE)[address % PAGE_SIZE] = value;
+            }
+        }
+        memory.len = len;
+        memory
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dense_memory_fails_for_address_above_max() {
+        // given
+        let mut mem = DenseMemory::<isize>::with_max_address(vec![1, 2, 3], 10);
+
+        // when
+        let result = mem.write(usize::MAX, 42);
+
+        // then
+        assert_eq!(
+            result,
+            Err(IntcodeError::AddressOutOfRange {
+                address: usize::MAX,
+                max_address: 10
+            })
+        );
+        assert_eq!(mem.len(), 3);
+        assert_eq!(mem.write(10, 42), Ok(()));
+        assert_eq!(mem.len(), 11);
+    }
+
+    #[test]
+    fn paged_memory_only_allocates_written_pages() {
+        // given
+        let mut mem = PagedMemory::<isize>::new(&[1, 2, 3]);
+
+        // when
+        mem.write(isize::MAX as usize, 42)
+            .expect("Expected valid write");
+
+        // then
+        assert_eq!(mem.page_count(), 2);
+        assert_eq!(mem.len(), isize::MAX as usize + 1);
+        assert_eq!(mem.read(1), 2);
+        assert_eq!(mem.read(isize::MAX as usize), 42);
+        assert_eq!(mem.read(isize::MAX as usize + 1), 0);
+        assert_eq!(mem.read(5_000), 0);
+    }
+
+    #[test]
+    fn paged_memory_clones_share_pages_until_written() {
+        // given
+        let program: Vec<isize> = (0..(PAGE_SIZE as isize * 3)).collect();
+        let original = PagedMemory::new(&program);
+
+        // when
+        let mut clone = original.clone();
+        let shared_before_write = clone.shared_pages(&original);
+        clone
+            .write(PAGE_SIZE + 1, -1)
+            .expect("Expected valid write");
+
+        // then
+        assert_eq!(shared_before_write, 3);
+        assert_eq!(clone.shared_pages(&original), 2);
+        assert_eq!(clone.read(PAGE_SIZE + 1), -1);
+        assert_eq!(original.read(PAGE_SIZE + 1), PAGE_SIZE as isize + 1);
+    }
+
+    #[test]
+    fn paged_memory_respects_max_address() {
+        // given
+        let mut mem = PagedMemory::<isize>::with_max_address(&[1, 2, 3], 4_096);
+
+        // when
+        let result = mem.write(4_097, 1);
+
+        // then
+        assert!(result.is_err());
+        assert_eq!(mem.page_count(), 1);
+        assert_eq!(mem.len(), 3);
+    }
+
+    #[test]
+    fn paged_memory_len_does_not_overflow_at_highest_address() {
+        // given
+        let mut mem = PagedMemory::<isize>::with_max_address(&[1, 2, 3], usize::MAX);
+
+        // when
+        let result1 = mem.write(usize::MAX, 1);
+        let result2 = mem.write(usize::MAX - 1, 2);
+
+        // then
+        assert_eq!(
+            result1,
+            Err(IntcodeError::AddressOutOfRange {
+                address: usize::MAX,
+                max_address: usize::MAX - 1
+            })
+        );
+        assert_eq!(result2, Ok(()));
+        assert_eq!(mem.len(), usize::MAX);
+        assert_eq!(mem.read(usize::MAX - 1), 2);
+        assert_eq!(
+            mem.blocks().last().map(|(_, cells)| cells.last()),
+            Some(Some(&2))
+        );
+    }
+
+    #[test]
+    fn paged_memory_splits_program_into_pages() {
+        // given
+        let program: Vec<isize> = (0..(PAGE_SIZE as isize * 2 + 5)).collect();
+
+        // when
+        let mem = PagedMemory::new(&program);
+
+        // then
+        assert_eq!(mem.page_count(), 3);
+        assert_eq!(mem.to_vec(), program);
+    }
+
+    #[test]
+    fn truncate_clears_cells_behind_new_end() {
+        // given
+        let mut paged = PagedMemory::<isize>::new(&[1, 2, 3]);
+        paged.write(5_000, 42).expect("Expected valid write");
+        paged.write(1_500, 7).expect("Expected valid write");
+        let mut dense = DenseMemory::new(paged.to_vec());
+
+        // when
+        paged.truncate(1_500);
+        dense.truncate(1_500);
+
+        // then
+        assert_eq!(paged.len(), 1_500);
+        assert_eq!(paged.page_count(), 2);
+        assert_eq!(paged.read(1_500), 0);
+        assert_eq!(paged.read(5_000), 0);
+        assert_eq!(paged.to_vec(), dense.to_vec());
+    }
+
+    #[test]
+    fn memory_can_be_rebuilt_from_blocks() {
+        // given
+        let mut paged = PagedMemory::<isize>::new(&[1, 2, 3]);
+        paged.write(5_000, 42).expect("Expected valid write");
+        let dense = DenseMemory::new(paged.to_vec());
+
+        // when
+        let blocks = paged.blocks();
+        let paged_copy = PagedMemory::from_blocks(blocks.clone(), paged.len(), paged.max_address());
+        let dense_copy = DenseMemory::from_blocks(blocks, dense.len(), dense.max_address());
+
+        // then
+        assert_eq!(paged.blocks().len(), 2);
+        assert_eq!(paged_copy, paged);
+        assert_eq!(dense_copy, dense);
+    }
+}
@@ -0,0 +1,697 @@
+// A network of machines that send each other packets, like the one of day 23.
+//
+// Every node runs a copy of the same program and boots with its address as first input. A
+// packet is written as the destination address followed by `payload_len` values; the payload
+// is appended to the input queue of the destination. The routing table maps addresses to nodes,
+// to special nodes (e.g. a NAT) or drops the packet. The simulation runs in rounds: every node
+// gets one turn per round, in which it runs until it needs input that is not there.
+use crate::{run_limited, Cell, DenseMemory, IntcodeError, Limits, Memory, ReturnStatus, State};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Display, Formatter};
+use std::io::Write;
+
+// Special nodes can answer each other (or themselves) forever, so a delivery drops the packets
+// for special nodes after this many replies
+const MAX_REPLIES_PER_DELIVERY: usize = 10_000;
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct Packet<C = isize> {
+    pub to: C,
+    pub payload: Vec<C>,
+}
+
+impl<C: Display> Display for Packet<C> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "-> {}:", self.to)?;
+        for value in &self.payload {
+            write!(f, " {}", value)?;
+        }
+        Ok(())
+    }
+}
+
+// Where a destination address leads to
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Route {
+    // the node with the given index
+    Node(usize),
+    // the special node with the given address
+    Special(isize),
+    Drop,
+}
+
+// Sender of a packet
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Endpoint {
+    Node(usize),
+    Special(isize),
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct LogEntry<C = isize> {
+    pub round: u64,
+    pub from: Endpoint,
+    pub packet: Packet<C>,
+    // where the packet ended up
+    pub route: Route,
+}
+
+impl<C: Display> Display for LogEntry<C> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "{:>6}: ", self.round)?;
+        match self.from {
+            Endpoint::Node(index) => write!(f, "node {} ", index)?,
+            Endpoint::Special(address) => write!(f, "special {} ", address)?,
+        }
+        write!(f, "{}", self.packet)?;
+        if self.route == Route::Drop {
+            write!(f, " (dropped)")?;
+        }
+        Ok(())
+    }
+}
+
+// A node that is not a machine, e.g. a NAT. Special nodes receive the packets routed to their
+// address and can send packets themselves.
+pub trait SpecialNode<C = isize> {
+    // Handle a packet sent to this node, returns the packets to send.
+    fn receive(&mut self, packet: &Packet<C>) -> Vec<Packet<C>>;
+
+    // Called when the network is idle, returns the packets to send.
+    fn idle(&mut self) -> Vec<Packet<C>> {
+        Vec::new()
+    }
+
+    // `true` stops the simulation
+    fn done(&self) -> bool {
+        false
+    }
+}
+
+// The NAT of day 23: remembers the last packet it received and sends its payload to `target`
+// when the network is idle. Done when it would send the same last payload value (the Y value)
+// twice in a row.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Nat<C = isize> {
+    target: C,
+    last: Option<Vec<C>>,
+    last_sent: Option<C>,
+    done: bool,
+}
+
+impl<C> Nat<C> {
+    pub fn new(target: C) -> Nat<C> {
+        Nat {
+            target,
+            last: None,
+            last_sent: None,
+            done: false,
+        }
+    }
+}
+
+impl<C: Cell> SpecialNode<C> for Nat<C> {
+    fn receive(&mut self, packet: &Packet<C>) -> Vec<Packet<C>> {
+        self.last = Some(packet.payload.clone());
+        Vec::new()
+    }
+
+    fn idle(&mut self) -> Vec<Packet<C>> {
+        let payload = match &self.last {
+            Some(payload) => payload.clone(),
+            None => return Vec::new(),
+        };
+        let last_value = payload.last().cloned();
+        if last_value.is_some() && last_value == self.last_sent {
+            self.done = true;
+            return Vec::new();
+        }
+        self.last_sent = last_value;
+        vec![Packet {
+            to: self.target.clone(),
+            payload,
+        }]
+    }
+
+    fn done(&self) -> bool {
+        self.done
+    }
+}
+
+// Writes every packet it receives as a line to `writer` and forwards it to `forward_to`, if set.
+// Write errors are ignored, logging must not change the simulation.
+pub struct Logger<W: Write, C = isize> {
+    writer: W,
+    forward_to: Option<C>,
+}
+
+impl<W: Write, C> Logger<W, C> {
+    pub fn new(writer: W, forward_to: Option<C>) -> Logger<W, C> {
+        Logger { writer, forward_to }
+    }
+}
+
+impl<W: Write, C: Cell> SpecialNode<C> for Logger<W, C> {
+    fn receive(&mut self, packet: &Packet<C>) -> Vec<Packet<C>> {
+        let _ = writeln!(self.writer, "{}", packet);
+        match &self.forward_to {
+            Some(to) => vec![Packet {
+                to: to.clone(),
+                payload: packet.payload.clone(),
+            }],
+            None => Vec::new(),
+        }
+    }
+}
+
+// A lossy link: forwards packets to `forward_to`, but drops each one with the given probability.
+// The decisions come from a seeded random number generator, so they are reproducible.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Dropper<C = isize> {
+    forward_to: C,
+    drop_probability: f64,
+    rng: Rng,
+}
+
+impl<C> Dropper<C> {
+    pub fn new(forward_to: C, drop_probability: f64, seed: u64) -> Dropper<C> {
+        Dropper {
+            forward_to,
+            drop_probability,
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl<C: Cell> SpecialNode<C> for Dropper<C> {
+    fn receive(&mut self, packet: &Packet<C>) -> Vec<Packet<C>> {
+        if self.rng.next_f64() < self.drop_probability {
+            return Vec::new();
+        }
+        vec![Packet {
+            to: self.forward_to.clone(),
+            payload: packet.payload.clone(),
+        }]
+    }
+}
+
+// Order of the turns within a round
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Schedule {
+    // by node index
+    RoundRobin,
+    // a new random order every round, reproducible with the same seed
+    Random { seed: u64 },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum NetworkStop {
+    AllHalted,
+    // the special node with the given address is done
+    Special(isize),
+    // `max_rounds` was reached
+    MaxRounds,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct NetworkReport<C = isize> {
+    pub stop: NetworkStop,
+    pub rounds: u64,
+    // every packet in the order it was sent
+    pub log: Vec<LogEntry<C>>,
+}
+
+// A machine of the network failed
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct NetworkError {
+    pub node: usize,
+    pub error: IntcodeError,
+}
+
+impl Display for NetworkError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "node {}: {}", self.node, self.error)
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+impl From<NetworkError> for String {
+    fn from(error: NetworkError) -> String {
+        error.to_string()
+    }
+}
+
+type BoxedSpecialNode<C> = Box<dyn SpecialNode<C>>;
+
+struct Node<M: Memory> {
+    state: State<M>,
+    input: VecDeque<M::Cell>,
+    // output that does not form a complete packet yet
+    output: Vec<M::Cell>,
+    halted: bool,
+}
+
+pub struct Network<M: Memory = DenseMemory> {
+    nodes: Vec<Node<M>>,
+    routes: HashMap<isize, Route>,
+    specials: Vec<(isize, BoxedSpecialNode<M::Cell>)>,
+    // number of values following the destination address of a packet
+    pub payload_len: usize,
+    // input for a node without queued input at the start of its turn (day 23: -1), `None` to
+    // skip the turn
+    pub idle_input: Option<M::Cell>,
+    pub schedule: Schedule,
+    pub max_rounds: Option<u64>,
+    // limits for a single turn, e.g. for nodes that never wait for input
+    pub turn_limits: Limits,
+}
+
+impl<M: Memory + Clone> Network<M> {
+    // `node_count` nodes running copies of `state`, node i has the address i. Packets have a
+    // payload of two values and nodes without input get -1, like on day 23.
+    pub fn new(state: State<M>, node_count: usize) -> Network<M> {
+        let nodes = (0..node_count)
+            .map(|address| Node {
+                state: state.clone(),
+                input: vec![M::Cell::from_isize(address as isize)].into(),
+                output: Vec::new(),
+                halted: false,
+            })
+            .collect();
+        let routes = (0..node_count)
+            .map(|address| (address as isize, Route::Node(address)))
+            .collect();
+        Network {
+            nodes,
+            routes,
+            specials: Vec::new(),
+            payload_len: 2,
+            idle_input: Some(M::Cell::from_isize(-1)),
+            schedule: Schedule::RoundRobin,
+            max_rounds: None,
+            turn_limits: Limits::unlimited(),
+        }
+    }
+}
+
+impl<M: Memory> Network<M> {
+    // Send packets for `address` to `route`. Unknown addresses are dropped.
+    pub fn route(&mut self, address: isize, route: Route) {
+        self.routes.insert(address, route);
+    }
+
+    // Add a special node and route its address to it
+    pub fn add_special(&mut self, address: isize, node: Box<dyn SpecialNode<M::Cell>>) {
+        self.specials.retain(|(a, _)| *a != address);
+        self.specials.push((address, node));
+        self.route(address, Route::Special(address));
+    }
+
+    // queue input for the node with the given index
+    pub fn send_to(&mut self, node: usize, values: &[M::Cell]) {
+        if let Some(node) = self.nodes.get_mut(node) {
+            node.input.extend(values.iter().cloned());
+        }
+    }
+
+    pub fn state(&self, node: usize) -> Option<&State<M>> {
+        self.nodes.get(node).map(|node| &node.state)
+    }
+
+    // Run rounds until all nodes halted, a special node is done or `max_rounds` is reached.
+    // The network is idle after a round in which no packet was sent and all input queues are
+    // empty; then the special nodes get the chance to send packets.
+    pub fn run(&mut self) -> Result<NetworkReport<M::Cell>, NetworkError> {
+        let mut rng = match self.schedule {
+            Schedule::RoundRobin => None,
+            Schedule::Random { seed } => Some(Rng::new(seed)),
+        };
+        let mut log: Vec<LogEntry<M::Cell>> = Vec::new();
+        let mut round: u64 = 0;
+        let stop = loop {
+            if self.max_rounds.map(|max| round >= max) == Some(true) {
+                break NetworkStop::MaxRounds;
+            }
+            if self.nodes.iter().all(|node| node.halted) {
+                break NetworkStop::AllHalted;
+            }
+            let mut order: Vec<usize> = (0..self.nodes.len()).collect();
+            if let Some(rng) = &mut rng {
+                rng.shuffle(&mut order);
+            }
+            let log_len = log.len();
+            for index in order {
+                for packet in self.turn(index)? {
+                    self.deliver(round, Endpoint::Node(index), packet, &mut log);
+                }
+            }
+            let idle = log.len() == log_len && self.nodes.iter().all(|n| n.input.is_empty());
+            if idle {
+                for i in 0..self.specials.len() {
+                    let (address, node) = &mut self.specials[i];
+                    let from = Endpoint::Special(*address);
+                    for packet in node.idle() {
+                        self.deliver(round, from, packet, &mut log);
+                    }
+                }
+            }
+            round += 1;
+            if let Some((address, _)) = self.specials.iter().find(|(_, node)| node.done()) {
+                break NetworkStop::Special(*address);
+            }
+        };
+        Ok(NetworkReport {
+            stop,
+            rounds: round,
+            log,
+        })
+    }
+
+    // Run the node until it waits for input and return the complete packets it sent
+    fn turn(&mut self, index: usize) -> Result<Vec<Packet<M::Cell>>, NetworkError> {
+        let node = &mut self.nodes[index];
+        if node.halted {
+            return Ok(Vec::new());
+        }
+        if node.input.is_empty() {
+            match &self.idle_input {
+                Some(value) => node.input.push_back(value.clone()),
+                None => return Ok(Vec::new()),
+            }
+        }
+        let status = run_limited(
+            &mut node.state,
+            &mut node.input,
+            &mut node.output,
+            &self.turn_limits,
+        )
+        .map_err(|error| NetworkError { node: index, error })?;
+        node.halted = status == ReturnStatus::Halt;
+        if node.halted {
+            // nothing reads the input of a halted node anymore
+            node.input.clear();
+        }
+
+        let frame_len = self.payload_len + 1;
+        let complete = node.output.len() / frame_len * frame_len;
+        let words: Vec<M::Cell> = node.output.drain(..complete).collect();
+        Ok(words
+            .chunks(frame_len)
+            .map(|frame| Packet {
+                to: frame[0].clone(),
+                payload: frame[1..].to_vec(),
+            })
+            .collect())
+    }
+
+    // route the packet and everything special nodes send in response
+    fn deliver(
+        &mut self,
+        round: u64,
+        from: Endpoint,
+        packet: Packet<M::Cell>,
+        log: &mut Vec<LogEntry<M::Cell>>,
+    ) {
+        let mut queue: VecDeque<(Endpoint, Packet<M::Cell>)> = VecDeque::new();
+        queue.push_back((from, packet));
+        let mut replies: usize = 0;
+        while let Some((from, packet)) = queue.pop_front() {
+            let route = packet
+                .to
+                .to_isize()
+                .and_then(|address| self.routes.get(&address))
+                .cloned()
+                .unwrap_or(Route::Drop);
+            let route = match route {
+                Route::Node(index) if index < self.nodes.len() && !self.nodes[index].halted => {
+                    self.nodes[index]
+                        .input
+                        .extend(packet.payload.iter().cloned());
+                    route
+                }
+                Route::Special(address) => {
+                    match self.specials.iter_mut().find(|(a, _)| *a == address) {
+                        Some((_, node)) if replies < MAX_REPLIES_PER_DELIVERY => {
+                            let sent = node.receive(&packet);
+                            replies += sent.len();
+                            queue.extend(sent.into_iter().map(|p| (Endpoint::Special(address), p)));
+                            route
+                        }
+                        _ => Route::Drop,
+                    }
+                }
+                _ => Route::Drop,
+            };
+            log.push(LogEntry {
+                round,
+                from,
+                packet,
+                route,
+            });
+        }
+    }
+}
+
+// xorshift64*, good enough for scheduling and dropping packets
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // the state must not be 0, xorshift only returns 0 after that
+        match seed ^ 0x9e37_79b9_7f4a_7c15 {
+            0 => Rng(0x9e37_79b9_7f4a_7c15),
+            state => Rng(state),
+        }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // uniformly distributed in [0, 1)
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // a number in 0..bound, `bound` must not be 0
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    pub(crate) fn shuffle<T>(&mut self, values: &mut [T]) {
+        for i in (1..values.len()).rev() {
+            values.swap(i, self.below(i as u64 + 1) as usize);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assemble;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Node 0 sends (42, 7) to node 1. Every node sends the packets it receives to 255.
+    const NODE: &str = "
+                IN [addr]
+                JNZ [addr], #loop
+                OUT #1
+                OUT #42
+                OUT #7
+        loop:   IN [x]
+                EQ [x], #-1, [tmp]
+                JNZ [tmp], #loop
+                IN [y]
+                OUT #255
+                OUT [x]
+                OUT [y]
+                JNZ #1, #loop
+        addr:   DATA 0
+        x:      DATA 0
+        y:      DATA 0
+        tmp:    DATA 0
+    ";
+
+    fn network(node_count: usize) -> Network {
+        let program = assemble(NODE).expect("Expected valid program");
+        Network::new(State::new(program), node_count)
+    }
+
+    fn summary(log: &[LogEntry]) -> Vec<(Endpoint, isize, Route)> {
+        log.iter()
+            .map(|entry| (entry.from, entry.packet.to, entry.route))
+            .collect()
+    }
+
+    #[test]
+    fn nat_stops_network_when_sending_the_same_value_twice() {
+        // given
+        let mut network = network(3);
+        network.add_special(255, Box::new(Nat::new(0)));
+
+        // when
+        let report = network.run().expect("Expected valid run");
+
+        // then
+        assert_eq!(report.stop, NetworkStop::Special(255));
+        assert_eq!(
+            summary(&report.log),
+            vec![
+                (Endpoint::Node(0), 1, Route::Node(1)),
+                (Endpoint::Node(1), 255, Route::Special(255)),
+                (Endpoint::Special(255), 0, Route::Node(0)),
+                (Endpoint::Node(0), 255, Route::Special(255)),
+            ]
+        );
+        assert_eq!(report.log[2].packet.payload, vec![42, 7]);
+        assert_eq!(report.log[2].to_string(), "     1: special 255 -> 0: 42 7");
+    }
+
+    // a `Write` whose content can be read after it was moved into a logger
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn special_nodes_log_forward_and_drop_packets() {
+        // given
+        let mut network = network(2);
+        let buffer = SharedBuffer::default();
+        network.add_special(255, Box::new(Logger::new(buffer.clone(), Some(100))));
+        network.add_special(100, Box::new(Dropper::new(1000, 1.0, 1)));
+        network.max_rounds = Some(5);
+
+        // when
+        let report = network.run().expect("Expected valid run");
+
+        // then
+        assert_eq!(report.stop, NetworkStop::MaxRounds);
+        assert_eq!(
+            summary(&report.log),
+            vec![
+                (Endpoint::Node(0), 1, Route::Node(1)),
+                (Endpoint::Node(1), 255, Route::Special(255)),
+                (Endpoint::Special(255), 100, Route::Special(100)),
+            ]
+        );
+        assert_eq!(
+            String::from_utf8(buffer.0.borrow().clone()).expect("Expected UTF-8"),
+            "-> 255: 42 7\n"
+        );
+    }
+
+    #[test]
+    fn nat_wakes_network_after_a_node_halted() {
+        // given
+        // node 0 halts, node 1 sends a packet to the NAT and one to node 0
+        let program = assemble(
+            "
+                    IN [addr]
+                    JZ [addr], #halt
+                    OUT #255
+                    OUT #5
+                    OUT #6
+                    OUT #0
+                    OUT #1
+                    OUT #2
+            loop:   IN [x]
+                    JNZ #1, #loop
+            halt:   HLT
+            addr:   DATA 0
+            x:      DATA 0
+            ",
+        )
+        .expect("Expected valid program");
+        let mut network = Network::new(State::new(program), 2);
+        network.add_special(255, Box::new(Nat::new(0)));
+        network.max_rounds = Some(100);
+
+        // when
+        let report = network.run().expect("Expected valid run");
+
+        // then
+        assert_eq!(report.stop, NetworkStop::Special(255));
+        assert_eq!(
+            summary(&report.log),
+            vec![
+                (Endpoint::Node(1), 255, Route::Special(255)),
+                (Endpoint::Node(1), 0, Route::Drop),
+                (Endpoint::Special(255), 0, Route::Drop),
+            ]
+        );
+    }
+
+    #[test]
+    fn replies_between_special_nodes_are_limited() {
+        // given
+        let mut network = network(2);
+        network.add_special(255, Box::new(Logger::new(std::io::sink(), Some(255))));
+        network.max_rounds = Some(3);
+
+        // when
+        let report = network.run().expect("Expected valid run");
+
+        // then
+        let replies = report
+            .log
+            .iter()
+            .filter(|entry| entry.from == Endpoint::Special(255))
+            .count();
+        assert_eq!(replies, MAX_REPLIES_PER_DELIVERY);
+        assert_eq!(
+            report.log.last().map(|entry| entry.route),
+            Some(Route::Drop)
+        );
+    }
+
+    #[test]
+    fn rng_does_not_get_stuck_for_any_seed() {
+        // given
+        let mut rng = Rng::new(0x9e37_79b9_7f4a_7c15);
+
+        // when
+        let values = [rng.next_u64(), rng.next_u64()];
+
+        // then
+        assert_ne!(values[0], values[1]);
+    }
+
+    #[test]
+    fn random_schedule_is_reproducible() {
+        // given
+        let run = |schedule: Schedule| {
+            let mut network = network(10);
+            network.schedule = schedule;
+            network.max_rounds = Some(3);
+            network.route(255, Route::Node(9));
+            network.run().expect("Expected valid run").log
+        };
+
+        // when
+        let log1 = run(Schedule::Random { seed: 7 });
+        let log2 = run(Schedule::Random { seed: 7 });
+        let round_robin = run(Schedule::RoundRobin);
+
+        // then
+        assert_eq!(log1, log2);
+        assert!(!log1.is_empty());
+        assert_eq!(round_robin[0].from, Endpoint::Node(0));
+        assert_eq!(round_robin[1].from, Endpoint::Node(1));
+        assert_eq!(round_robin[1].round, 0);
+    }
+}
@@ -0,0 +1,419 @@
+// An instruction profiler, used as `TraceSink` with `run_traced`.
+//
+// Besides counting executions per address and per opcode and the outcomes of the conditional
+// jumps, the profiler keeps a guess of the call stack: compiled intcode programs allocate a
+// stack frame by increasing the relative base at the start of a function and free it before
+// returning. An `ARB` with a positive offset therefore counts as a call of a function named
+// after the address of the `ARB`, and a decrease of the relative base below the base of a frame
+// as its return. Every instruction is counted for the stack it was executed in, which gives
+// the folded stacks of flame graph tools ("main;fn@12;fn@80 1234").
+//
+// Not every `ARB` is a call, e.g. a loop may move the relative base through an array. An `ARB`
+// that runs again while its frame is the innermost one therefore opens no new frame, which also
+// folds direct recursion into one frame, and the stack is cut off at `MAX_STACK_DEPTH` frames.
+use crate::disasm::format_instruction;
+use crate::{Cell, Instruction, Mode, Opcode, TraceEntry, TraceSink};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+const MAX_STACK_DEPTH: usize = 64;
+
+// node of `CallTree` for the stack without any function
+const MAIN: usize = 0;
+
+// Outcomes of a conditional jump
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
+pub struct BranchCount {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+// A loop found by a backward jump: the instructions from `start` to `end` (the address of the
+// jump)
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct HotLoop {
+    pub start: usize,
+    pub end: usize,
+    // how often the jump back was taken
+    pub iterations: u64,
+    // instructions executed at the addresses of the loop
+    pub instructions: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Profiler {
+    pub total: u64,
+    // (execution count, instruction text) by address
+    addresses: BTreeMap<usize, (u64, String)>,
+    opcodes: BTreeMap<Opcode, u64>,
+    branches: BTreeMap<usize, BranchCount>,
+    // taken jumps to constant addresses by (source, target), to find loops
+    jumps: BTreeMap<(usize, usize), u64>,
+    // (function address, relative base before the call, call tree node) of the open frames
+    frames: Vec<(usize, isize, usize)>,
+    stacks: CallTree,
+}
+
+// The call stacks seen so far as a tree, so counting an instruction for the current stack does
+// not depend on the depth of the stack
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct CallTree {
+    // (parent node, function address) by node id, `MAIN` is its own parent
+    nodes: Vec<(usize, usize)>,
+    // node id by (parent node, function address)
+    children: HashMap<(usize, usize), usize>,
+    // executed instructions by node id
+    counts: Vec<u64>,
+}
+
+impl Default for CallTree {
+    fn default() -> CallTree {
+        CallTree {
+            nodes: vec![(MAIN, 0)],
+            children: HashMap::new(),
+            counts: vec![0],
+        }
+    }
+}
+
+impl CallTree {
+    // the node of `function` called in the stack of `parent`, created if necessary
+    fn child(&mut self, parent: usize, function: usize) -> usize {
+        let nodes = &mut self.nodes;
+        let counts = &mut self.counts;
+        *self.children.entry((parent, function)).or_insert_with(|| {
+            nodes.push((parent, function));
+            counts.push(0);
+            nodes.len() - 1
+        })
+    }
+
+    // the functions of the stack of `node`, outermost first
+    fn stack(&self, mut node: usize) -> Vec<usize> {
+        let mut stack = Vec::new();
+        while node != MAIN {
+            let (parent, function) = self.nodes[node];
+            stack.push(function);
+            node = parent;
+        }
+        stack.reverse();
+        stack
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    // execution count of the instruction at `address`
+    pub fn count(&self, address: usize) -> u64 {
+        self.addresses.get(&address).map_or(0, |(count, _)| *count)
+    }
+
+    pub fn opcode_count(&self, opcode: Opcode) -> u64 {
+        self.opcodes.get(&opcode).copied().unwrap_or(0)
+    }
+
+    pub fn branch(&self, address: usize) -> Option<BranchCount> {
+        self.branches.get(&address).copied()
+    }
+
+    // The addresses that were executed most, with their counts
+    pub fn hot_addresses(&self, n: usize) -> Vec<(usize, u64)> {
+        let mut addresses: Vec<(usize, u64)> = self
+            .addresses
+            .iter()
+            .map(|(address, (count, _))| (*address, *count))
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        addresses.truncate(n);
+        addresses
+    }
+
+    // Loops by the number of instructions executed in them, most expensive first. Nested loops
+    // are listed separately, the outer loop includes the instructions of the inner one.
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        let mut loops: Vec<HotLoop> = self
+            .jumps
+            .iter()
+            .filter(|((source, target), _)| target <= source)
+            .map(|((source, target), iterations)| HotLoop {
+                start: *target,
+                end: *source,
+                iterations: *iterations,
+                instructions: self
+                    .addresses
+                    .range(*target..=*source)
+                    .map(|(_, (count, _))| *count)
+                    .sum(),
+            })
+            .collect();
+        loops.sort_by(|a, b| {
+            b.instructions
+                .cmp(&a.instructions)
+                .then(a.start.cmp(&b.start))
+        });
+        loops
+    }
+
+    // The call stacks in the folded format of flame graph tools, one line per stack
+    pub fn folded_stacks(&self) -> String {
+        let mut stacks: Vec<(Vec<usize>, u64)> = (0..self.stacks.nodes.len())
+            .filter(|node| self.stacks.counts[*node] > 0)
+            .map(|node| (self.stacks.stack(node), self.stacks.counts[node]))
+            .collect();
+        stacks.sort();
+        let mut text = String::new();
+        for (stack, count) in &stacks {
+            let mut line = "main".to_owned();
+            for function in stack {
+                // writing to a string can not fail
+                let _ = write!(line, ";fn@{}", function);
+            }
+            let _ = writeln!(text, "{} {}", line, count);
+        }
+        text
+    }
+
+    // A human readable report of the `top` hottest addresses and loops, the opcodes and the
+    // conditional jumps
+    pub fn report(&self, top: usize) -> String {
+        let percent = |count: u64| 100.0 * count as f64 / self.total.max(1) as f64;
+        let mut text = String::new();
+        // writing to a string can not fail
+        let _ = writeln!(text, "{} instructions executed", self.total);
+
+        let _ = writeln!(text, "\nby opcode:");
+        let mut opcodes: Vec<(&Opcode, &u64)> = self.opcodes.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1));
+        for (opcode, count) in opcodes {
+            let _ = writeln!(
+                text,
+                "  {:<4} {:>12} {:>6.2}%",
+                opcode.mnemonic(),
+                count,
+                percent(*count)
+            );
+        }
+
+        let _ = writeln!(text, "\nhot addresses:");
+        for (address, count) in self.hot_addresses(top) {
+            let instruction = &self.addresses[&address].1;
+            let _ = writeln!(
+                text,
+                "  {:>6} {:>12} {:>6.2}%  {}",
+                address,
+                count,
+                percent(count),
+                instruction
+            );
+        }
+
+        let _ = writeln!(text, "\nhot loops:");
+        for hot_loop in self.hot_loops().iter().take(top) {
+            let _ = writeln!(
+                text,
+                "  {:>6}..{:<6} {:>12} {:>6.2}%  {} iterations",
+                hot_loop.start,
+                hot_loop.end,
+                hot_loop.instructions,
+                percent(hot_loop.instructions),
+                hot_loop.iterations
+            );
+        }
+
+        let _ = writeln!(text, "\nconditional jumps:");
+        for (address, branch) in &self.branches {
+            let _ = writeln!(
+                text,
+                "  {:>6} taken {:>12} not taken {:>12}  {}",
+                address, branch.taken, branch.not_taken, self.addresses[address].1
+            );
+        }
+        text
+    }
+}
+
+// value of the nth parameter of the traced instruction
+fn param_value<C: Cell>(entry: &TraceEntry<C>, n: usize) -> Option<C> {
+    match entry.step.instruction.modes[n] {
+        Mode::Immediate => entry.params.get(n).cloned(),
+        _ => entry.step.reads[n].as_ref().map(|(_, value)| value.clone()),
+    }
+}
+
+// `Some(true)` if the traced instruction is a conditional jump that jumped, `None` if it is no
+// conditional jump
+pub(crate) fn jump_taken<C: Cell>(entry: &TraceEntry<C>) -> Option<bool> {
+    let opcode = entry.step.instruction.opcode;
+    if opcode != Opcode::Jnz && opcode != Opcode::Jz {
+        return None;
+    }
+    let condition = param_value(entry, 0).map(|value| value != C::zero());
+    Some(condition == Some(opcode == Opcode::Jnz))
+}
+
+impl<C: Cell> TraceSink<C> for Profiler {
+    fn record(&mut self, entry: &TraceEntry<C>) {
+        let address = entry.step.address;
+        let instruction: &Instruction = &entry.step.instruction;
+        self.total += 1;
+        self.addresses
+            .entry(address)
+            .or_insert_with(|| (0, format_instruction(instruction, &entry.params)))
+            .0 += 1;
+        *self.opcodes.entry(instruction.opcode).or_insert(0) += 1;
+        let node = self.frames.last().map_or(MAIN, |(_, _, node)| *node);
+        self.stacks.counts[node] += 1;
+
+        match instruction.opcode {
+            Opcode::Jnz | Opcode::Jz => {
+                let taken = jump_taken(entry) == Some(true);
+                let branch = self.branches.entry(address).or_default();
+                if taken {
+                    branch.taken += 1;
+                    // only jumps to a constant address can form loops, other jumps are
+                    // usually returns
+                    let target = match instruction.modes[1] {
+                        Mode::Immediate => param_value(entry, 1).and_then(|value| value.to_isize()),
+                        _ => None,
+                    };
+                    if let Some(target) = target.filter(|target| *target >= 0) {
+                        *self.jumps.entry((address, target as usize)).or_insert(0) += 1;
+                    }
+                } else {
+                    branch.not_taken += 1;
+                }
+            }
+            Opcode::Arb => {
+                let offset = param_value(entry, 0).and_then(|value| value.to_isize());
+                let rel_base = offset.and_then(|offset| entry.rel_base.checked_add(offset));
+                match (offset, rel_base) {
+                    (Some(offset), Some(_)) if offset > 0 => {
+                        let innermost = self.frames.last().map(|(function, _, _)| *function);
+                        if innermost != Some(address) && self.frames.len() < MAX_STACK_DEPTH {
+                            let node = self.stacks.child(node, address);
+                            self.frames.push((address, entry.rel_base, node));
+                        }
+                    }
+                    (Some(_), Some(rel_base)) => {
+                        while self.frames.last().map(|(_, base, _)| *base >= rel_base) == Some(true)
+                        {
+                            self.frames.pop();
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{assemble, run_traced, Limits, ReturnStatus, State};
+
+    // calls a function that loops 3 times, twice
+    const PROGRAM: &str = "
+                ARB #stack
+                ADD #0, #after1, rb+0
+                JNZ #1, #function
+        after1: ADD #0, #after2, rb+0
+                JNZ #1, #function
+        after2: HLT
+        17:
+        function:
+                ARB #2
+                ADD #0, #3, rb+0
+        23:
+        loop:   ADD rb+0, #-1, rb+0
+                JNZ rb+0, #loop
+                ARB #-2
+                JZ #0, rb+0
+        stack:  DATA 0, 0, 0, 0
+    ";
+
+    #[test]
+    fn profiler_counts_instructions_branches_and_stacks() {
+        // given
+        let program = assemble(PROGRAM).expect("Expected valid program");
+        let mut state: State = State::new(program);
+        let mut profiler = Profiler::new();
+
+        // when
+        let status = run_traced(
+            &mut state,
+            &mut None,
+            &mut Vec::new(),
+            &Limits::unlimited(),
+            &mut profiler,
+        );
+
+        // then
+        assert_eq!(status, Ok(ReturnStatus::Halt));
+        // 6 in main, 2 * (2 + 2 * 3 + 2) in the function
+        assert_eq!(profiler.total, 26);
+        assert_eq!(profiler.opcode_count(Opcode::Arb), 5);
+        assert_eq!(profiler.count(23), 6);
+        assert_eq!(
+            profiler.branch(27),
+            Some(BranchCount {
+                taken: 4,
+                not_taken: 2
+            })
+        );
+        // the returns are indirect jumps, not loops
+        assert_eq!(
+            profiler.hot_loops(),
+            vec![HotLoop {
+                start: 23,
+                end: 27,
+                iterations: 4,
+                instructions: 12
+            }]
+        );
+        // the first ARB sets up the stack, so everything after it counts as in function 0
+        assert_eq!(
+            profiler.folded_stacks(),
+            "main 1\nmain;fn@0 9\nmain;fn@0;fn@17 16\n"
+        );
+    }
+
+    #[test]
+    fn profiler_does_not_open_a_frame_per_loop_iteration() {
+        // given
+        // moves the relative base forward in every iteration and never back
+        let program = assemble(
+            "
+                    ADD #0, #1000, [n]
+            4:
+            loop:   ARB #1
+                    ADD [n], #-1, [n]
+                    JNZ [n], #loop
+                    HLT
+            n:      DATA 0
+            ",
+        )
+        .expect("Expected valid program");
+        let mut state: State = State::new(program);
+        let mut profiler = Profiler::new();
+
+        // when
+        let status = run_traced(
+            &mut state,
+            &mut None,
+            &mut Vec::new(),
+            &Limits::unlimited(),
+            &mut profiler,
+        );
+
+        // then
+        assert_eq!(status, Ok(ReturnStatus::Halt));
+        assert_eq!(profiler.total, 3002);
+        assert_eq!(profiler.frames.len(), 1);
+        assert_eq!(profiler.folded_stacks(), "main 2\nmain;fn@4 3000\n");
+    }
+}
@@ -0,0 +1,511 @@
+// Saving and loading machine states.
+//
+// Both formats store the memory as blocks of cells; runs of zeros are left out. The binary
+// format starts with "ICSN" and a version byte and ends with a checksum of everything before
+// it. The text format looks like this:
+//
+//     # intcode snapshot
+//     version: 1
+//     ip: 4
+//     rel_base: 0
+//     status: wait
+//     len: 41
+//     max_address: 16777215
+//     mem 0: 3,12,4,12,1001,12,-1,12,1005,12,2,99,5
+//     mem 40: 7
+//
+// `mem` lines hold at most 16 cells; cells that are not listed are 0.
+//
+// `len` and `max_address` come from the file, so loading checks them against a limit before
+// any memory is allocated: `DEFAULT_MAX_ADDRESS` or the one passed to the `_with_max_address`
+// functions.
+use crate::codec::{
+    checksum, line_error, text_lines, write_cell, write_signed, write_text_header, write_unsigned,
+    DecodeError, Reader,
+};
+use crate::{Cell, DenseMemory, Memory, ReturnStatus, State, DEFAULT_MAX_ADDRESS};
+use std::fmt::Write;
+
+const BINARY_MAGIC: &[u8] = b"ICSN";
+const TEXT_HEADER: &str = "# intcode snapshot";
+const VERSION: u8 = 1;
+
+// zero runs of at least this length split a block
+const MIN_ZERO_RUN: usize = 8;
+const CELLS_PER_LINE: usize = 16;
+
+// A machine state and the status of the run that produced it, so the run can be continued
+// after loading
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct Snapshot<M = DenseMemory> {
+    pub state: State<M>,
+    // `None` if the state was never run
+    pub status: Option<ReturnStatus>,
+}
+
+impl<M: Memory> Snapshot<M> {
+    pub fn new(state: State<M>, status: Option<ReturnStatus>) -> Snapshot<M> {
+        Snapshot { state, status }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let blocks = self.state.mem.blocks();
+        let blocks = compact_blocks(&blocks);
+        let cell_count: usize = blocks.iter().map(|(_, cells)| cells.len()).sum();
+        let mut out = Vec::with_capacity(cell_count * 2 + 64);
+        out.extend_from_slice(BINARY_MAGIC);
+        out.push(VERSION);
+        write_unsigned(&mut out, self.state.ip as u128);
+        write_signed(&mut out, self.state.rel_base);
+        out.push(status_code(&self.status));
+        write_unsigned(&mut out, self.state.mem.len() as u128);
+        write_unsigned(&mut out, self.state.mem.max_address() as u128);
+        write_unsigned(&mut out, blocks.len() as u128);
+        for (start, cells) in &blocks {
+            write_unsigned(&mut out, *start as u128);
+            write_unsigned(&mut out, cells.len() as u128);
+            for cell in *cells {
+                write_cell(&mut out, cell);
+            }
+        }
+        let sum = checksum(&out);
+        out.extend_from_slice(&sum.to_le_bytes());
+        out
+    }
+
+    pub fn to_text(&self) -> String {
+        let blocks = self.state.mem.blocks();
+        let blocks = compact_blocks(&blocks);
+        let cell_count: usize = blocks.iter().map(|(_, cells)| cells.len()).sum();
+        let mut text = String::with_capacity(cell_count * 6 + 128);
+        let status = match &self.status {
+            None => "none",
+            Some(ReturnStatus::Halt) => "halt",
+            Some(ReturnStatus::Wait) => "wait",
+            Some(ReturnStatus::OutOfFuel) => "out-of-fuel",
+            Some(ReturnStatus::OutputFull) => "output-full",
+        };
+        write_text_header(&mut text, TEXT_HEADER, VERSION);
+        // writing to a string can not fail
+        let _ = writeln!(text, "ip: {}", self.state.ip);
+        let _ = writeln!(text, "rel_base: {}", self.state.rel_base);
+        let _ = writeln!(text, "status: {}", status);
+        let _ = writeln!(text, "len: {}", self.state.mem.len());
+        let _ = writeln!(text, "max_address: {}", self.state.mem.max_address());
+        for (start, cells) in blocks {
+            for (i, chunk) in cells.chunks(CELLS_PER_LINE).enumerate() {
+                let values: Vec<String> = chunk.iter().map(|c| c.to_string()).collect();
+                let _ = writeln!(
+                    text,
+                    "mem {}: {}",
+                    start + i * CELLS_PER_LINE,
+                    values.join(",")
+                );
+            }
+        }
+        text
+    }
+
+    // Load a snapshot in either format
+    pub fn read(bytes: &[u8]) -> Result<Snapshot<M>, DecodeError> {
+        Snapshot::read_with_max_address(bytes, DEFAULT_MAX_ADDRESS)
+    }
+
+    // Like `read`, but accepts snapshots with a maximum address up to `limit`
+    pub fn read_with_max_address(bytes: &[u8], limit: usize) -> Result<Snapshot<M>, DecodeError> {
+        if bytes.starts_with(BINARY_MAGIC) {
+            Snapshot::from_bytes_with_max_address(bytes, limit)
+        } else {
+            let text = std::str::from_utf8(bytes).map_err(|e| DecodeError {
+                offset: e.valid_up_to(),
+                message: "neither a binary snapshot nor valid UTF-8".to_owned(),
+            })?;
+            Snapshot::from_text_with_max_address(text, limit)
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot<M>, DecodeError> {
+        Snapshot::from_bytes_with_max_address(bytes, DEFAULT_MAX_ADDRESS)
+    }
+
+    pub fn from_bytes_with_max_address(
+        bytes: &[u8],
+        limit: usize,
+    ) -> Result<Snapshot<M>, DecodeError> {
+        if !bytes.starts_with(BINARY_MAGIC) {
+            return Err(DecodeError {
+                offset: 0,
+                message: "not an intcode snapshot".to_owned(),
+            });
+        }
+        if bytes.len() < BINARY_MAGIC.len() + 9 {
+            return Err(DecodeError {
+                offset: bytes.len(),
+                message: "unexpected end of data".to_owned(),
+            });
+        }
+        let (content, sum) = bytes.split_at(bytes.len() - 8);
+        let mut expected = [0u8; 8];
+        expected.copy_from_slice(sum);
+        if checksum(content) != u64::from_le_bytes(expected) {
+            return Err(DecodeError {
+                offset: content.len(),
+                message: "checksum mismatch, the snapshot is damaged".to_owned(),
+            });
+        }
+
+        let mut reader = Reader::new(content);
+        reader.bytes(BINARY_MAGIC.len())?;
+        let version = reader.byte()?;
+        if version != VERSION {
+            return Err(reader.error(format!("unsupported snapshot version {}", version)));
+        }
+        let ip = reader.usize()?;
+        let rel_base = reader.signed()?;
+        let status = match reader.byte()? {
+            0 => None,
+            1 => Some(ReturnStatus::Halt),
+            2 => Some(ReturnStatus::Wait),
+            3 => Some(ReturnStatus::OutOfFuel),
+            4 => Some(ReturnStatus::OutputFull),
+            code => return Err(reader.error(format!("unknown status {}", code))),
+        };
+        let len = reader.usize()?;
+        let max_address = reader.usize()?;
+        let mut layout = Layout::new(len, max_address, limit).map_err(|e| reader.error(e))?;
+        let block_count = reader.usize()?;
+        let mut blocks = Vec::new();
+        for _ in 0..block_count {
+            let start = reader.usize()?;
+            let count = reader.usize()?;
+            layout
+                .add_block(start, count)
+                .map_err(|e| reader.error(e))?;
+            let cells = (0..count)
+                .map(|_| reader.cell())
+                .collect::<Result<Vec<M::Cell>, DecodeError>>()?;
+            blocks.push((start, cells));
+        }
+        if !reader.is_at_end() {
+            return Err(reader.error("unexpected data after the memory".to_owned()));
+        }
+        Ok(Snapshot::from_parts(ip, rel_base, status, blocks, layout))
+    }
+
+    pub fn from_text(text: &str) -> Result<Snapshot<M>, DecodeError> {
+        Snapshot::from_text_with_max_address(text, DEFAULT_MAX_ADDRESS)
+    }
+
+    pub fn from_text_with_max_address(
+        text: &str,
+        limit: usize,
+    ) -> Result<Snapshot<M>, DecodeError> {
+        let mut lines = text_lines(text, TEXT_HEADER, VERSION)?;
+        let line_error = |line_nr: usize, message: String| line_error(text, line_nr, message);
+
+        // the header fields in this order, with their line numbers
+        let mut fields: Vec<(usize, &str)> = Vec::with_capacity(5);
+        for key in &["ip", "rel_base", "status", "len", "max_address"] {
+            let (line_nr, line) = lines
+                .next()
+                .ok_or_else(|| line_error(text.lines().count(), format!("missing '{}'", key)))?;
+            match line.split_once(':') {
+                Some((k, value)) if k.trim() == *key => fields.push((line_nr, value.trim())),
+                _ => return Err(line_error(line_nr, format!("expected '{}: ...'", key))),
+            }
+        }
+        let number = |index: usize| -> Result<i128, DecodeError> {
+            let (line_nr, value) = fields[index];
+            value
+                .parse::<i128>()
+                .map_err(|e| line_error(line_nr, format!("invalid number '{}': {}", value, e)))
+        };
+        let in_range = |index: usize, min: i128, max: i128| -> Result<i128, DecodeError> {
+            let value = number(index)?;
+            if value < min || value > max {
+                return Err(line_error(
+                    fields[index].0,
+                    format!("{} is out of range", value),
+                ));
+            }
+            Ok(value)
+        };
+        let ip = in_range(0, 0, usize::MAX as i128)? as usize;
+        let rel_base = in_range(1, isize::MIN as i128, isize::MAX as i128)? as isize;
+        let status = match fields[2].1 {
+            "none" => None,
+            "halt" => Some(ReturnStatus::Halt),
+            "wait" => Some(ReturnStatus::Wait),
+            "out-of-fuel" => Some(ReturnStatus::OutOfFuel),
+            "output-full" => Some(ReturnStatus::OutputFull),
+            status => {
+                return Err(line_error(
+                    fields[2].0,
+                    format!("unknown status '{}'", status),
+                ))
+            }
+        };
+        let len = in_range(3, 0, usize::MAX as i128)? as usize;
+        let max_address = in_range(4, 0, usize::MAX as i128)? as usize;
+        let mut layout = Layout::new(len, max_address, limit).map_err(|e| {
+            let line_nr = if max_address > limit {
+                fields[4].0
+            } else {
+                fields[3].0
+            };
+            line_error(line_nr, e)
+        })?;
+
+        let mut blocks = Vec::new();
+        for (line_nr, line) in lines {
+            let error = |message: String| line_error(line_nr, message);
+            let (start, values) = line
+                .strip_prefix("mem ")
+                .and_then(|rest| rest.split_once(':'))
+                .ok_or_else(|| error("expected 'mem <address>: <values>'".to_owned()))?;
+            let start = start
+                .trim()
+                .parse::<usize>()
+                .map_err(|e| error(format!("invalid address '{}': {}", start.trim(), e)))?;
+            let cells = values
+                .split(',')
+                .map(|value| M::Cell::from_token(value.trim()))
+                .collect::<Result<Vec<M::Cell>, String>>()
+                .map_err(error)?;
+            layout.add_block(start, cells.len()).map_err(error)?;
+            blocks.push((start, cells));
+        }
+        Ok(Snapshot::from_parts(ip, rel_base, status, blocks, layout))
+    }
+
+    fn from_parts(
+        ip: usize,
+        rel_base: isize,
+        status: Option<ReturnStatus>,
+        blocks: Vec<(usize, Vec<M::Cell>)>,
+        layout: Layout,
+    ) -> Snapshot<M> {
+        let mem = M::from_blocks(blocks, layout.len, layout.max_address);
+        Snapshot {
+            state: State { mem, ip, rel_base },
+            status,
+        }
+    }
+}
+
+fn status_code(status: &Option<ReturnStatus>) -> u8 {
+    match status {
+        None => 0,
+        Some(ReturnStatus::Halt) => 1,
+        Some(ReturnStatus::Wait) => 2,
+        Some(ReturnStatus::OutOfFuel) => 3,
+        Some(ReturnStatus::OutputFull) => 4,
+    }
+}
+
+// Split the blocks at long runs of zeros and drop the zeros
+fn compact_blocks<C: Cell>(blocks: &[(usize, Vec<C>)]) -> Vec<(usize, &[C])> {
+    let zero = C::zero();
+    let mut result = Vec::new();
+    for (start, cells) in blocks {
+        let mut i = 0;
+        while i < cells.len() {
+            if cells[i] == zero {
+                i += 1;
+                continue;
+            }
+            let begin = i;
+            // one past the last non-zero cell
+            let mut end = i;
+            while i < cells.len() {
+                if cells[i] != zero {
+                    end = i + 1;
+                } else if i + 1 - end >= MIN_ZERO_RUN {
+                    break;
+                }
+                i += 1;
+            }
+            result.push((start + begin, &cells[begin..end]));
+            i = end;
+        }
+    }
+    result
+}
+
+// Checks that the memory blocks of a snapshot are valid
+struct Layout {
+    len: usize,
+    max_address: usize,
+    // end of the previous block
+    end: usize,
+}
+
+impl Layout {
+    // `limit` is the highest maximum address the caller accepts
+    fn new(len: usize, max_address: usize, limit: usize) -> Result<Layout, String> {
+        if max_address > limit {
+            return Err(format!(
+                "maximum address {} exceeds the limit {}",
+                max_address, limit
+            ));
+        }
+        if len > 0 && len - 1 > max_address {
+            return Err(format!(
+                "memory length {} exceeds the maximum address {}",
+                len, max_address
+            ));
+        }
+        Ok(Layout {
+            len,
+            max_address,
+            end: 0,
+        })
+    }
+
+    fn add_block(&mut self, start: usize, count: usize) -> Result<(), String> {
+        if start < self.end {
+            return Err(format!(
+                "memory block at {} overlaps the previous block or is out of order",
+                start
+            ));
+        }
+        match start.checked_add(count) {
+            Some(end) if end <= self.len => {
+                self.end = end;
+                Ok(())
+            }
+            _ => Err(format!(
+                "memory block at {} with {} cells exceeds the memory length {}",
+                start, count, self.len
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_programs::COUNTDOWN;
+    use crate::{run, PagedMemory};
+
+    fn waiting_state() -> State {
+        let mut state = State::new(COUNTDOWN.to_vec());
+        state.mem.write(40, 7).expect("Expected valid write");
+        state.rel_base = -3;
+        state
+    }
+
+    #[test]
+    fn snapshot_round_trips_in_both_formats() {
+        // given
+        let snapshot = Snapshot::new(waiting_state(), Some(ReturnStatus::Wait));
+
+        // when
+        let bytes = snapshot.to_bytes();
+        let text = snapshot.to_text();
+
+        // then
+        assert_eq!(Snapshot::read(&bytes), Ok(snapshot.clone()));
+        assert_eq!(Snapshot::read(text.as_bytes()), Ok(snapshot));
+        assert_eq!(
+            text,
+            "# intcode snapshot\nversion: 1\nip: 0\nrel_base: -3\nstatus: wait\nlen: 41\n\
+             max_address: 16777215\nmem 0: 3,12,4,12,1001,12,-1,12,1005,12,2,99\nmem 40: 7\n"
+        );
+    }
+
+    #[test]
+    fn restored_snapshot_continues_the_run() {
+        // given
+        let mut state = State::new(COUNTDOWN.to_vec());
+        let status = run(&mut state, &mut None, &mut Vec::new()).expect("Expected valid run");
+        let bytes = Snapshot::new(state.clone(), Some(status)).to_bytes();
+
+        // when
+        let mut restored =
+            Snapshot::<DenseMemory>::from_bytes(&bytes).expect("Expected valid data");
+        let mut output = Vec::new();
+        let mut restored_output = Vec::new();
+        let status1 = run(&mut state, &mut Some(3), &mut output);
+        let status2 = run(&mut restored.state, &mut Some(3), &mut restored_output);
+
+        // then
+        assert_eq!(restored.status, Some(ReturnStatus::Wait));
+        assert_eq!(status1, Ok(ReturnStatus::Halt));
+        assert_eq!(status2, Ok(ReturnStatus::Halt));
+        assert_eq!(restored_output, vec![3, 2, 1]);
+        assert_eq!(restored.state, state);
+    }
+
+    #[test]
+    fn paged_memory_snapshot_only_stores_used_cells() {
+        // given
+        let mut state = State::with_memory(PagedMemory::new(COUNTDOWN));
+        state
+            .mem
+            .write(isize::MAX as usize, 1)
+            .expect("Expected valid write");
+        let snapshot = Snapshot::new(state, None);
+
+        // when
+        let bytes = snapshot.to_bytes();
+
+        // then
+        assert!(bytes.len() < 100);
+        assert!(Snapshot::<PagedMemory>::read(&bytes).is_err());
+        assert_eq!(
+            Snapshot::read_with_max_address(&bytes, isize::MAX as usize),
+            Ok(snapshot)
+        );
+    }
+
+    #[test]
+    fn damaged_snapshots_are_rejected() {
+        // given
+        let bytes = Snapshot::new(waiting_state(), None).to_bytes();
+        let text = Snapshot::new(waiting_state(), None).to_text();
+        let mut damaged = bytes.clone();
+        damaged[8] ^= 1;
+
+        // when
+        let results: Vec<Result<Snapshot, DecodeError>> = vec![
+            Snapshot::read(&damaged),
+            Snapshot::read(&bytes[..bytes.len() - 1]),
+            Snapshot::read(b"ICSN"),
+            Snapshot::read(text.replace("len: 41", "len: 20").as_bytes()),
+            Snapshot::read(text.replace("mem 40", "mem 4").as_bytes()),
+            Snapshot::read(text.replace("status: none", "status: ok").as_bytes()),
+            Snapshot::read(text.replace("ip: 0\n", "").as_bytes()),
+            Snapshot::read(text.replace(",99", ",x").as_bytes()),
+            Snapshot::read(b"\xff"),
+            Snapshot::read(text.replace("len: 41", "len: 1000000000000").as_bytes()),
+            Snapshot::read(
+                text.replace("len: 41", "len: 1000000000000")
+                    .replace("max_address: 16777215", "max_address: 1000000000000")
+                    .as_bytes(),
+            ),
+        ];
+
+        // then
+        let messages: Vec<String> = results
+            .into_iter()
+            .map(|r| r.expect_err("Expected error").message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "checksum mismatch, the snapshot is damaged",
+                "checksum mismatch, the snapshot is damaged",
+                "unexpected end of data",
+                "line 9: memory block at 40 with 1 cells exceeds the memory length 20",
+                "line 9: memory block at 4 overlaps the previous block or is out of order",
+                "line 5: unknown status 'ok'",
+                "line 3: expected 'ip: ...'",
+                "line 8: invalid digit found in string",
+                "neither a binary snapshot nor valid UTF-8",
+                "line 6: memory length 1000000000000 exceeds the maximum address 16777215",
+                "line 7: maximum address 1000000000000 exceeds the limit 16777215",
+            ]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{
+    check_operand_count, get_valid_address, get_value, to_isize, Cell, Instruction, IntcodeError,
+    IntcodeInput, Memory, Mode, Opcode, State,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum StepEvent<C = isize> {
+    // an instruction without I/O was executed
+    Continue,
+    // the given input value was consumed
+    Input(C),
+    // the given value was written to the output
+    Output(C),
+    // the instruction needs input, but none was given. Nothing was executed.
+    InputRequired,
+    // the program reached the halt instruction. Nothing was executed.
+    Halt,
+}
+
+// Everything that happened during the execution of a single instruction
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct Step<C = isize> {
+    // address of the instruction
+    pub address: usize,
+    pub instruction: Instruction,
+    // (address, value) of the memory cells read for the first two parameters. Parameters in
+    // immediate mode do not read from memory.
+    pub reads: [Option<(usize, C)>; 2],
+    // (address, value) of the memory cell that was written, if any
+    pub write: Option<(usize, C)>,
+    pub event: StepEvent<C>,
+}
+
+impl<M: Memory> State<M> {
+    // Execute exactly one instruction. `input` is only consumed if the instruction is an input
+    // instruction; `StepEvent::Input` tells the caller when this is the case.
+    // If the instruction fails, the state is left unchanged.
+    pub fn step(&mut self, mut input: Option<M::Cell>) -> Result<Step<M::Cell>, IntcodeError> {
+        self.step_with(&mut input)
+    }
+
+    // Like `step`, but input is only read from `input` if the instruction needs it.
+    pub fn step_with<I>(&mut self, input: &mut I) -> Result<Step<M::Cell>, IntcodeError>
+    where
+        I: IntcodeInput<M::Cell> + ?Sized,
+    {
+        let address = self.ip;
+        if address >= self.mem.len() {
+            return Err(IntcodeError::IpOutOfBounds {
+                ip: address,
+                mem_size: self.mem.len(),
+            });
+        }
+        let instruction = Instruction::decode(address, to_isize(&self.mem.read(address))?)?;
+        check_operand_count(address, &self.mem, instruction.opcode.param_count())?;
+
+        let mut step = Step {
+            address,
+            instruction,
+            reads: [None, None],
+            write: None,
+            event: StepEvent::Continue,
+        };
+
+        match instruction.opcode {
+            Opcode::Add => self.binary_op(&mut step, |v1, v2| v1.try_add(v2))?,
+            Opcode::Mul => self.binary_op(&mut step, |v1, v2| v1.try_mul(v2))?,
+            Opcode::Lt => self.binary_op(&mut step, |v1, v2| Some(bool_cell(v1 < v2)))?,
+            Opcode::Eq => self.binary_op(&mut step, |v1, v2| Some(bool_cell(v1 == v2)))?,
+            Opcode::In => {
+                let dest = self.dest_param(&step, 0)?;
+                if let Some(value) = input.read() {
+                    self.write(&mut step, dest, value.clone())?;
+                    step.event = StepEvent::Input(value);
+                    self.ip += 2;
+                } else {
+                    step.event = StepEvent::InputRequired;
+                }
+            }
+            Opcode::Out => {
+                step.event = StepEvent::Output(self.param(&mut step, 0)?);
+                self.ip += 2;
+            }
+            Opcode::Jnz => self.jump_if(&mut step, |condition| *condition != M::Cell::zero())?,
+            Opcode::Jz => self.jump_if(&mut step, |condition| *condition == M::Cell::zero())?,
+            Opcode::Arb => {
+                let offset = to_isize(&self.param(&mut step, 0)?)?;
+                self.rel_base = self
+                    .rel_base
+                    .checked_add(offset)
+                    .ok_or(IntcodeError::Overflow { address })?;
+                self.ip += 2;
+            }
+            Opcode::Hlt => {
+                step.event = StepEvent::Halt;
+            }
+        }
+        Ok(step)
+    }
+
+    // Address the current instruction would write to, `None` if it does not write or can not be
+    // decoded
+    pub(crate) fn write_address(&self) -> Option<usize> {
+        let instruction =
+            Instruction::decode(self.ip, to_isize(&self.mem.read(self.ip)).ok()?).ok()?;
+        let n = match instruction.opcode {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq => 2,
+            Opcode::In => 0,
+            _ => return None,
+        };
+        let raw_address = to_isize(&self.mem.read(self.ip + n + 1)).ok()?;
+        get_valid_address(raw_address, instruction.modes[n], self.rel_base).ok()
+    }
+
+    // `op` returns `None` if the operation overflows
+    fn binary_op<F>(&mut self, step: &mut Step<M::Cell>, op: F) -> Result<(), IntcodeError>
+    where
+        F: Fn(&M::Cell, &M::Cell) -> Option<M::Cell>,
+    {
+        let v1 = self.param(step, 0)?;
+        let v2 = self.param(step, 1)?;
+        let dest = self.dest_param(step, 2)?;
+        let result = op(&v1, &v2).ok_or(IntcodeError::Overflow {
+            address: step.address,
+        })?;
+        self.write(step, dest, result)?;
+        self.ip += 4;
+        Ok(())
+    }
+
+    fn jump_if<F>(&mut self, step: &mut Step<M::Cell>, condition: F) -> Result<(), IntcodeError>
+    where
+        F: Fn(&M::Cell) -> bool,
+    {
+        let value = self.param(step, 0)?;
+        let dest = self.param(step, 1)?;
+        self.ip = if condition(&value) {
+            get_valid_address(to_isize(&dest)?, Mode::Position, 0)?
+        } else {
+            self.ip + 3
+        };
+        Ok(())
+    }
+
+    // value of the nth (starting at 0) parameter of the current instruction
+    fn param(&self, step: &mut Step<M::Cell>, n: usize) -> Result<M::Cell, IntcodeError> {
+        let raw_value = self.mem.read(step.address + n + 1);
+        let mode = step.instruction.modes[n];
+        if mode == Mode::Immediate {
+            return Ok(raw_value);
+        }
+        let address = get_valid_address(to_isize(&raw_value)?, mode, self.rel_base)?;
+        let value = get_value(raw_value, mode, &self.mem, self.rel_base)?;
+        step.reads[n] = Some((address, value.clone()));
+        Ok(value)
+    }
+
+    // address the nth (starting at 0) parameter of the current instruction points to. Fails if
+    // the address can not be written to.
+    fn dest_param(&self, step: &Step<M::Cell>, n: usize) -> Result<usize, IntcodeError> {
+        let address = get_valid_address(
+            to_isize(&self.mem.read(step.address + n + 1))?,
+            step.instruction.modes[n],
+            self.rel_base,
+        )?;
+        self.mem.check_address(address)?;
+        Ok(address)
+    }
+
+    fn write(
+        &mut self,
+        step: &mut Step<M::Cell>,
+        address: usize,
+        value: M::Cell,
+    ) -> Result<(), IntcodeError> {
+        self.mem.write(address, value.clone())?;
+        step.write = Some((address, value));
+        Ok(())
+    }
+}
+
+pub(crate) fn bool_cell<C: Cell>(value: bool) -> C {
+    if value {
+        C::one()
+    } else {
+        C::zero()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::DenseMemory;
+
+    #[test]
+    fn step_reports_reads_and_writes() {
+        // given
+        let mut state = State::<DenseMemory>::new(vec![21001, 5, 7, 6, 99, 30]);
+        state.rel_base = 3;
+
+        // when
+        let step = state.step(None).expect("Expected successful step");
+
+        // then
+        assert_eq!(step.address, 0);
+        assert_eq!(step.instruction.opcode, Opcode::Add);
+        assert_eq!(step.reads, [Some((5, 30)), None]);
+        assert_eq!(step.write, Some((9, 37)));
+        assert_eq!(step.event, StepEvent::Continue);
+        assert_eq!(state.ip, 4);
+        assert_eq!(state.mem.read(9), 37);
+    }
+
+    #[test]
+    fn step_does_not_execute_input_without_value() {
+        // given
+        let mut state = State::<DenseMemory>::new(vec![3, 3, 99, 0]);
+
+        // when
+        let blocked = state.step(None).expect("Expected successful step");
+
+        // then
+        assert_eq!(blocked.event, StepEvent::InputRequired);
+        assert_eq!(blocked.write, None);
+        assert_eq!(state.ip, 0);
+
+        // when
+        let read = state.step(Some(42)).expect("Expected successful step");
+
+        // then
+        assert_eq!(read.event, StepEvent::Input(42));
+        assert_eq!(read.write, Some((3, 42)));
+        assert_eq!(state.ip, 2);
+    }
+
+    #[test]
+    fn step_ignores_input_for_other_instructions() {
+        // given
+        let mut state = State::<DenseMemory>::new(vec![104, 13, 99]);
+
+        // when
+        let output = state.step(Some(1)).expect("Expected successful step");
+        let halt = state.step(Some(1)).expect("Expected successful step");
+
+        // then
+        assert_eq!(output.event, StepEvent::Output(13));
+        assert_eq!(halt.event, StepEvent::Halt);
+        assert_eq!(state.ip, 2);
+    }
+
+    #[test]
+    fn step_leaves_state_unchanged_on_error() {
+        // given
+        let mut state = State::<DenseMemory>::new(vec![104, 1, 2201, -5, 0, 0, 99]);
+        state.step(None).expect("Expected successful step");
+        let before = state.clone();
+
+        // when
+        let result = state.step(None);
+
+        // then
+        assert_eq!(result, Err(IntcodeError::NegativeAddress { address: -5 }));
+        assert_eq!(state, before);
+    }
+}
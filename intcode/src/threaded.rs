@@ -0,0 +1,373 @@
+// Machines that run on their own threads, connected by `std::sync::mpsc` channels.
+//
+// All channels of a cluster share a monitor that knows how many machines are running and how
+// many of them wait for input. When every running machine waits on an empty channel, nothing
+// can ever be sent again: the cluster detects this deadlock and stops the waiting machines with
+// `IntcodeError::Deadlock` instead of blocking forever.
+use crate::{
+    run, DenseMemory, IntcodeError, IntcodeInput, IntcodeOutput, Memory, ReturnStatus, RunError,
+    RunResult, State,
+};
+use std::collections::HashSet;
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+// What a machine does if it needs input and its channel is empty
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum InputPolicy<C = isize> {
+    // wait until a value arrives (day 7)
+    Blocking,
+    // continue with the given value right away (day 23 uses -1)
+    NonBlocking(C),
+}
+
+#[derive(Debug, Default)]
+struct Monitor {
+    running: usize,
+    // ids of the channels with a waiting receiver
+    waiting: HashSet<usize>,
+    deadlock: bool,
+}
+
+type Shared = Arc<(Mutex<Monitor>, Condvar)>;
+
+fn lock(shared: &Shared) -> MutexGuard<'_, Monitor> {
+    // a machine thread can not panic while holding the lock, so it is never poisoned
+    shared.0.lock().expect("Cluster monitor poisoned")
+}
+
+pub struct ClusterSender<C = isize> {
+    id: usize,
+    sender: mpsc::Sender<C>,
+    shared: Shared,
+}
+
+impl<C> ClusterSender<C> {
+    // Values sent to a receiver that was dropped are lost.
+    pub fn send(&self, value: C) {
+        let mut monitor = lock(&self.shared);
+        if self.sender.send(value).is_ok() && monitor.waiting.remove(&self.id) {
+            self.shared.1.notify_all();
+        }
+    }
+}
+
+impl<C> Clone for ClusterSender<C> {
+    fn clone(&self) -> ClusterSender<C> {
+        ClusterSender {
+            id: self.id,
+            sender: self.sender.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<C> IntcodeOutput<C> for ClusterSender<C> {
+    fn write(&mut self, value: C) {
+        self.send(value);
+    }
+}
+
+pub struct ClusterReceiver<C = isize> {
+    id: usize,
+    receiver: mpsc::Receiver<C>,
+    shared: Shared,
+}
+
+impl<C> ClusterReceiver<C> {
+    // all values that are in the channel right now, e.g. to read the output of the cluster
+    pub fn try_iter(&self) -> mpsc::TryIter<'_, C> {
+        self.receiver.try_iter()
+    }
+
+    // Wait for the next value, `None` on a deadlock
+    fn recv(&self) -> Option<C> {
+        let mut monitor = lock(&self.shared);
+        loop {
+            if let Ok(value) = self.receiver.try_recv() {
+                return Some(value);
+            }
+            if monitor.deadlock {
+                return None;
+            }
+            monitor.waiting.insert(self.id);
+            if monitor.waiting.len() >= monitor.running {
+                monitor.deadlock = true;
+                self.shared.1.notify_all();
+                return None;
+            }
+            monitor = self
+                .shared
+                .1
+                .wait(monitor)
+                .expect("Cluster monitor poisoned");
+        }
+    }
+}
+
+// the input of a machine thread
+struct MachineInput<C> {
+    receiver: ClusterReceiver<C>,
+    policy: InputPolicy<C>,
+}
+
+impl<C: Clone> IntcodeInput<C> for MachineInput<C> {
+    fn read(&mut self) -> Option<C> {
+        match &self.policy {
+            InputPolicy::Blocking => self.receiver.recv(),
+            InputPolicy::NonBlocking(idle_value) => {
+                self.receiver.receiver.try_recv().ok().or_else(|| {
+                    // polling machines should not keep the other threads from running
+                    thread::yield_now();
+                    Some(idle_value.clone())
+                })
+            }
+        }
+    }
+}
+
+// the output of a machine thread: everything is collected and sent on if connected
+struct MachineOutput<C> {
+    sender: Option<ClusterSender<C>>,
+    values: Vec<C>,
+}
+
+impl<C: Clone> IntcodeOutput<C> for MachineOutput<C> {
+    fn write(&mut self, value: C) {
+        if let Some(sender) = &self.sender {
+            sender.send(value.clone());
+        }
+        self.values.push(value);
+    }
+}
+
+struct Machine<M: Memory> {
+    state: State<M>,
+    input: MachineInput<M::Cell>,
+    output: Option<ClusterSender<M::Cell>>,
+}
+
+// A set of machines and the channels between them. Values from outside (e.g. phase settings)
+// are sent before `run`; while the cluster runs, only the machines send.
+pub struct Cluster<M: Memory = DenseMemory> {
+    shared: Shared,
+    machines: Vec<Machine<M>>,
+    channel_count: usize,
+}
+
+impl<M> Cluster<M>
+where
+    M: Memory + Send + 'static,
+    M::Cell: Send + 'static,
+{
+    pub fn new() -> Cluster<M> {
+        Cluster {
+            shared: Arc::new((Mutex::new(Monitor::default()), Condvar::new())),
+            machines: Vec::new(),
+            channel_count: 0,
+        }
+    }
+
+    pub fn channel(&mut self) -> (ClusterSender<M::Cell>, ClusterReceiver<M::Cell>) {
+        let (sender, receiver) = mpsc::channel();
+        let id = self.channel_count;
+        self.channel_count += 1;
+        (
+            ClusterSender {
+                id,
+                sender,
+                shared: self.shared.clone(),
+            },
+            ClusterReceiver {
+                id,
+                receiver,
+                shared: self.shared.clone(),
+            },
+        )
+    }
+
+    // Add a machine that reads from `input` and writes to `output`. Returns the index of its
+    // result in `run`.
+    pub fn add(
+        &mut self,
+        state: State<M>,
+        input: ClusterReceiver<M::Cell>,
+        output: Option<ClusterSender<M::Cell>>,
+        policy: InputPolicy<M::Cell>,
+    ) -> usize {
+        self.machines.push(Machine {
+            state,
+            input: MachineInput {
+                receiver: input,
+                policy,
+            },
+            output,
+        });
+        self.machines.len() - 1
+    }
+
+    // Run every machine on its own thread until all of them halted, failed or are deadlocked.
+    // The output of each machine is collected in its result.
+    pub fn run(self) -> Vec<RunResult<M>> {
+        let Cluster {
+            shared, machines, ..
+        } = self;
+        lock(&shared).running = machines.len();
+        let handles: Vec<thread::JoinHandle<RunResult<M>>> = machines
+            .into_iter()
+            .map(|machine| {
+                let shared = shared.clone();
+                thread::spawn(move || run_machine(machine, &shared))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("Machine thread panicked"))
+            .collect()
+    }
+}
+
+impl<M> Default for Cluster<M>
+where
+    M: Memory + Send + 'static,
+    M::Cell: Send + 'static,
+{
+    fn default() -> Cluster<M> {
+        Cluster::new()
+    }
+}
+
+fn run_machine<M: Memory>(machine: Machine<M>, shared: &Shared) -> RunResult<M> {
+    let Machine {
+        mut state,
+        mut input,
+        output,
+    } = machine;
+    let mut output = MachineOutput {
+        sender: output,
+        values: Vec::new(),
+    };
+    let result = run(&mut state, &mut input, &mut output);
+
+    // the remaining machines may now wait for this one forever
+    let mut monitor = lock(shared);
+    monitor.running -= 1;
+    monitor.waiting.remove(&input.receiver.id);
+    if monitor.running > 0 && monitor.waiting.len() >= monitor.running {
+        monitor.deadlock = true;
+    }
+    shared.1.notify_all();
+    drop(monitor);
+
+    let error = match result {
+        Ok(ReturnStatus::Wait) => IntcodeError::Deadlock,
+        Ok(status) => return Ok((state, status, output.values)),
+        Err(error) => error,
+    };
+    Err(RunError {
+        error,
+        state,
+        output: output.values,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cluster_runs_the_day_7_feedback_loop() {
+        // given
+        let program = vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let mut cluster = Cluster::new();
+        let (mut senders, receivers): (Vec<ClusterSender>, Vec<ClusterReceiver>) =
+            (0..5).map(|_| cluster.channel()).unzip();
+        for (phase, sender) in [9, 8, 7, 6, 5].iter().zip(&senders) {
+            sender.send(*phase);
+        }
+        senders[0].send(0);
+        senders.rotate_left(1);
+        for (input, output) in receivers.into_iter().zip(senders) {
+            let state = State::new(program.clone());
+            cluster.add(state, input, Some(output), InputPolicy::Blocking);
+        }
+
+        // when
+        let results = cluster.run();
+
+        // then
+        let last_outputs: Vec<isize> = results
+            .into_iter()
+            .map(|result| {
+                let (_, status, output) = result.expect("Expected valid run");
+                assert_eq!(status, ReturnStatus::Halt);
+                *output.last().expect("Expected output")
+            })
+            .collect();
+        assert_eq!(last_outputs[4], 139629729);
+    }
+
+    #[test]
+    fn cluster_detects_deadlock() {
+        // given
+        // both machines read before they write anything
+        let program: Vec<isize> = vec![3, 7, 4, 7, 1105, 1, 0, 0];
+        let mut cluster = Cluster::new();
+        let (sender_a, receiver_a) = cluster.channel();
+        let (sender_b, receiver_b) = cluster.channel();
+        cluster.add(
+            State::new(program.clone()),
+            receiver_a,
+            Some(sender_b),
+            InputPolicy::Blocking,
+        );
+        cluster.add(
+            State::new(program),
+            receiver_b,
+            Some(sender_a),
+            InputPolicy::Blocking,
+        );
+
+        // when
+        let results = cluster.run();
+
+        // then
+        for result in results {
+            let error = result.expect_err("Expected deadlock");
+            assert_eq!(error.error, IntcodeError::Deadlock);
+            assert_eq!(error.state.ip, 0);
+        }
+    }
+
+    #[test]
+    fn non_blocking_policy_uses_idle_value() {
+        // given
+        // outputs the first two input values
+        let program: Vec<isize> = vec![3, 9, 4, 9, 3, 9, 4, 9, 99, 0];
+        let mut cluster = Cluster::new();
+        let (sender, receiver) = cluster.channel();
+        let (output, results) = cluster.channel();
+        sender.send(42);
+        cluster.add(
+            State::new(program),
+            receiver,
+            Some(output),
+            InputPolicy::NonBlocking(-1),
+        );
+
+        // when
+        let status: Vec<ReturnStatus> = cluster
+            .run()
+            .into_iter()
+            .map(|result| result.expect("Expected valid run").1)
+            .collect();
+
+        // then
+        assert_eq!(status, vec![ReturnStatus::Halt]);
+        assert_eq!(results.try_iter().collect::<Vec<isize>>(), vec![42, -1]);
+    }
+}
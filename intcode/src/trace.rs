@@ -0,0 +1,422 @@
+use crate::codec::{write_cell, write_signed, write_unsigned, DecodeError, Reader};
+use crate::disasm::format_instruction;
+use crate::{Cell, Instruction, IntcodeError, IntcodeInput, Memory, State, Step, StepEvent};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+
+// One executed instruction, as seen by a `TraceSink`
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct TraceEntry<C = isize> {
+    // relative base before the instruction was executed
+    pub rel_base: isize,
+    // the raw parameter words of the instruction, before it was executed
+    pub params: Vec<C>,
+    // address, decoded instruction, values read, memory write and I/O of the instruction
+    pub step: Step<C>,
+}
+
+// Renders the entry as a single line, e.g.
+// "    12: ADD [100], #5, rb+3 | rb=4 [100]=7 [7]<-12"
+impl<C: Cell> Display for TraceEntry<C> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "{:>6}: {} | rb={}",
+            self.step.address,
+            format_instruction(&self.step.instruction, &self.params),
+            self.rel_base
+        )?;
+        for (address, value) in self.step.reads.iter().flatten() {
+            write!(f, " [{}]={}", address, value)?;
+        }
+        if let Some((address, value)) = &self.step.write {
+            write!(f, " [{}]<-{}", address, value)?;
+        }
+        match &self.step.event {
+            StepEvent::Input(value) => write!(f, " in={}", value),
+            StepEvent::Output(value) => write!(f, " out={}", value),
+            StepEvent::Halt => write!(f, " halt"),
+            StepEvent::Continue | StepEvent::InputRequired => Ok(()),
+        }
+    }
+}
+
+impl<M: Memory> State<M> {
+    // Like `step_with`, but also returns the relative base and the raw parameters from before
+    // the instruction was executed.
+    pub fn step_traced<I>(&mut self, input: &mut I) -> Result<TraceEntry<M::Cell>, IntcodeError>
+    where
+        I: IntcodeInput<M::Cell> + ?Sized,
+    {
+        let rel_base = self.rel_base;
+        let mut params: Vec<M::Cell> = (1..=3).map(|i| self.mem.read(self.ip + i)).collect();
+        let step = self.step_with(input)?;
+        params.truncate(step.instruction.opcode.param_count());
+        Ok(TraceEntry {
+            rel_base,
+            params,
+            step,
+        })
+    }
+}
+
+// Receives every instruction executed by `run_traced`. Instructions that block on missing
+// input are not traced, they are traced when they are executed after resuming.
+pub trait TraceSink<C = isize> {
+    fn record(&mut self, entry: &TraceEntry<C>);
+
+    // `run_traced` skips building the entries if this returns false
+    fn enabled(&self) -> bool {
+        true
+    }
+}
+
+// discards everything, used by `run` and `run_limited`
+impl<C> TraceSink<C> for () {
+    fn record(&mut self, _entry: &TraceEntry<C>) {}
+
+    fn enabled(&self) -> bool {
+        false
+    }
+}
+
+impl<C, T: TraceSink<C> + ?Sized> TraceSink<C> for &mut T {
+    fn record(&mut self, entry: &TraceEntry<C>) {
+        (**self).record(entry)
+    }
+
+    fn enabled(&self) -> bool {
+        (**self).enabled()
+    }
+}
+
+// Writes one line per instruction in the format of `TraceEntry`'s `Display` implementation.
+// Tracing must not change the result of a run, so write errors are only remembered and
+// returned by `finish`. Nothing is written after the first error.
+pub struct TextTraceSink<W: Write> {
+    writer: W,
+    error: Option<std::io::Error>,
+}
+
+impl<W: Write> TextTraceSink<W> {
+    pub fn new(writer: W) -> TextTraceSink<W> {
+        TextTraceSink {
+            writer,
+            error: None,
+        }
+    }
+
+    // flush the writer and return it, or the first error that occurred
+    pub fn finish(mut self) -> Result<W, std::io::Error> {
+        match self.error {
+            Some(error) => Err(error),
+            None => self.writer.flush().map(|_| self.writer),
+        }
+    }
+}
+
+impl TextTraceSink<std::io::Stderr> {
+    pub fn stderr() -> TextTraceSink<std::io::Stderr> {
+        TextTraceSink::new(std::io::stderr())
+    }
+}
+
+impl<C: Cell, W: Write> TraceSink<C> for TextTraceSink<W> {
+    fn record(&mut self, entry: &TraceEntry<C>) {
+        if self.error.is_none() {
+            self.error = writeln!(self.writer, "{}", entry).err();
+        }
+    }
+}
+
+const BINARY_MAGIC: &[u8] = b"ICTR";
+const BINARY_VERSION: u8 = 1;
+
+const READ_0: u8 = 1;
+const READ_1: u8 = 2;
+const WRITE: u8 = 4;
+
+// Writes a compact binary log that can be read back with `read_binary_trace`.
+//
+// Format: "ICTR", version byte, then per instruction: address, instruction word, relative base,
+// raw parameters, a flag byte (which reads/writes follow, event kind in the upper bits),
+// reads and writes as (address, value), and the I/O value for input and output events.
+// Write errors are handled like in `TextTraceSink`.
+pub struct BinaryTraceSink<W: Write> {
+    writer: W,
+    buffer: Vec<u8>,
+    error: Option<std::io::Error>,
+}
+
+impl<W: Write> BinaryTraceSink<W> {
+    pub fn new(writer: W) -> BinaryTraceSink<W> {
+        let mut buffer = Vec::with_capacity(64);
+        buffer.extend_from_slice(BINARY_MAGIC);
+        buffer.push(BINARY_VERSION);
+        BinaryTraceSink {
+            writer,
+            buffer,
+            error: None,
+        }
+    }
+
+    // flush the writer and return it, or the first error that occurred
+    pub fn finish(mut self) -> Result<W, std::io::Error> {
+        self.flush_buffer();
+        match self.error {
+            Some(error) => Err(error),
+            None => self.writer.flush().map(|_| self.writer),
+        }
+    }
+
+    fn flush_buffer(&mut self) {
+        if self.error.is_none() {
+            self.error = self.writer.write_all(&self.buffer).err();
+        }
+        self.buffer.clear();
+    }
+}
+
+impl<C: Cell, W: Write> TraceSink<C> for BinaryTraceSink<W> {
+    fn record(&mut self, entry: &TraceEntry<C>) {
+        let step = &entry.step;
+        let out = &mut self.buffer;
+        write_unsigned(out, step.address as u128);
+        write_signed(out, step.instruction.encode());
+        write_signed(out, entry.rel_base);
+        for param in &entry.params {
+            write_cell(out, param);
+        }
+        let (event, value) = match &step.event {
+            StepEvent::Continue => (0, None),
+            StepEvent::Input(value) => (1, Some(value)),
+            StepEvent::Output(value) => (2, Some(value)),
+            StepEvent::InputRequired => (3, None),
+            StepEvent::Halt => (4, None),
+        };
+        let mut flags = event << 4;
+        if step.reads[0].is_some() {
+            flags |= READ_0;
+        }
+        if step.reads[1].is_some() {
+            flags |= READ_1;
+        }
+        if step.write.is_some() {
+            flags |= WRITE;
+        }
+        out.push(flags);
+        for (address, value) in step.reads.iter().flatten().chain(&step.write) {
+            write_unsigned(out, *address as u128);
+            write_cell(out, value);
+        }
+        if let Some(value) = value {
+            write_cell(out, value);
+        }
+        if self.buffer.len() >= 4096 {
+            self.flush_buffer();
+        }
+    }
+}
+
+// Decode a log written by `BinaryTraceSink`
+pub fn read_binary_trace<C: Cell>(bytes: &[u8]) -> Result<Vec<TraceEntry<C>>, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    if reader.bytes(BINARY_MAGIC.len())? != BINARY_MAGIC {
+        return Err(DecodeError {
+            offset: 0,
+            message: "not an intcode trace".to_owned(),
+        });
+    }
+    let version = reader.byte()?;
+    if version != BINARY_VERSION {
+        return Err(reader.error(format!("unsupported trace version {}", version)));
+    }
+
+    let mut entries = Vec::new();
+    while !reader.is_at_end() {
+        let address = reader.usize()?;
+        let instruction = Instruction::decode(address, reader.signed()?)
+            .map_err(|e| reader.error(e.to_string()))?;
+        let rel_base = reader.signed()?;
+        let params = (0..instruction.opcode.param_count())
+            .map(|_| reader.cell())
+            .collect::<Result<Vec<C>, DecodeError>>()?;
+        let flags = reader.byte()?;
+        let mut read_access = |flag: u8| -> Result<Option<(usize, C)>, DecodeError> {
+            if flags & flag == 0 {
+                return Ok(None);
+            }
+            Ok(Some((reader.usize()?, reader.cell()?)))
+        };
+        let reads = [read_access(READ_0)?, read_access(READ_1)?];
+        let write = read_access(WRITE)?;
+        let event = match flags >> 4 {
+            0 => StepEvent::Continue,
+            1 => StepEvent::Input(reader.cell()?),
+            2 => StepEvent::Output(reader.cell()?),
+            3 => StepEvent::InputRequired,
+            4 => StepEvent::Halt,
+            kind => return Err(reader.error(format!("unknown event kind {}", kind))),
+        };
+        entries.push(TraceEntry {
+            rel_base,
+            params,
+            step: Step {
+                address,
+                instruction,
+                reads,
+                write,
+                event,
+            },
+        });
+    }
+    Ok(entries)
+}
+
+// Keeps the last `capacity` entries in memory, e.g. to look at the instructions that led to an
+// error.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct RingBufferSink<C = isize> {
+    entries: VecDeque<TraceEntry<C>>,
+    capacity: usize,
+}
+
+impl<C> RingBufferSink<C> {
+    pub fn new(capacity: usize) -> RingBufferSink<C> {
+        RingBufferSink {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    // the recorded entries, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry<C>> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn into_vec(self) -> Vec<TraceEntry<C>> {
+        self.entries.into()
+    }
+}
+
+impl<C: Clone> TraceSink<C> for RingBufferSink<C> {
+    fn record(&mut self, entry: &TraceEntry<C>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry.clone());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_programs::COUNTDOWN;
+    use crate::{run_traced, Limits, ReturnStatus, State};
+
+    #[test]
+    fn ring_buffer_keeps_last_instructions_before_error() {
+        // given
+        // the last instruction jumps to an unknown opcode
+        let mut state: State = State::new(vec![1101, 2, 3, 7, 1105, 1, 7, 0]);
+        let mut sink = RingBufferSink::new(2);
+
+        // when
+        let result = run_traced(
+            &mut state,
+            &mut None,
+            &mut Vec::new(),
+            &Limits::unlimited(),
+            &mut sink,
+        );
+
+        // then
+        assert!(result.is_err());
+        let lines: Vec<String> = sink.entries().map(|e| e.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "     0: ADD #2, #3, [7] | rb=0 [7]<-5",
+                "     4: JNZ #1, #7 | rb=0"
+            ]
+        );
+        assert_eq!(state.ip, 7);
+    }
+
+    #[test]
+    fn text_sink_writes_one_line_per_instruction() {
+        // given
+        let mut state = State::new(COUNTDOWN.to_vec());
+        let mut sink = TextTraceSink::new(Vec::new());
+        let mut output = Vec::new();
+
+        // when
+        let status = run_traced(
+            &mut state,
+            &mut Some(2),
+            &mut output,
+            &Limits::unlimited(),
+            &mut sink,
+        )
+        .expect("Expected valid run");
+
+        // then
+        assert_eq!(status, ReturnStatus::Halt);
+        assert_eq!(output, vec![2, 1]);
+        let text = String::from_utf8(sink.finish().expect("Expected no error")).expect("UTF-8");
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "     0: IN [12] | rb=0 [12]<-2 in=2");
+        assert_eq!(lines[1], "     2: OUT [12] | rb=0 [12]=2 out=2");
+        assert_eq!(
+            lines[2],
+            "     4: ADD [12], #-1, [12] | rb=0 [12]=2 [12]<-1"
+        );
+        assert_eq!(lines[7], "    11: HLT | rb=0 halt");
+    }
+
+    #[test]
+    fn binary_trace_round_trips() {
+        // given
+        let mut state = State::new(COUNTDOWN.to_vec());
+        let mut binary = BinaryTraceSink::new(Vec::new());
+        let mut ring = RingBufferSink::new(100);
+
+        // when
+        run_traced(
+            &mut state,
+            &mut Some(3),
+            &mut Vec::new(),
+            &Limits::unlimited(),
+            &mut binary,
+        )
+        .expect("Expected valid run");
+        let mut state = State::new(COUNTDOWN.to_vec());
+        run_traced(
+            &mut state,
+            &mut Some(3),
+            &mut Vec::new(),
+            &Limits::unlimited(),
+            &mut ring,
+        )
+        .expect("Expected valid run");
+        let bytes = binary.finish().expect("Expected no error");
+
+        // then
+        assert_eq!(read_binary_trace::<isize>(&bytes), Ok(ring.into_vec()));
+        assert!(read_binary_trace::<isize>(&bytes[..bytes.len() - 1]).is_err());
+        assert!(read_binary_trace::<isize>(b"nope").is_err());
+    }
+}
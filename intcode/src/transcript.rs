@@ -0,0 +1,433 @@
+// Recording and replaying the I/O of a session.
+//
+// A transcript lists the values a program read and wrote, in order, and the resume points at
+// which the machine blocked because it needed more input than it got. Replaying a transcript
+// runs the program again with the recorded input, checks that it writes the same output and
+// blocks at the same points, and reports the first divergence. The text format looks like this:
+//
+//     # intcode transcript
+//     version: 1
+//     out: 63,10
+//     wait
+//     in: 104,105,10
+//     out: 1000
+//     halt
+//
+// `in` and `out` lines hold at most 16 values, `wait` marks a resume point.
+use crate::codec::{line_error, text_lines, write_text_header, DecodeError};
+use crate::io::{at_halt, run_steps};
+use crate::{
+    Cell, DenseMemory, IntcodeError, IntcodeInput, IntcodeOutput, Limits, Memory, ReturnStatus,
+    State, StepEvent,
+};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Write};
+
+const TEXT_HEADER: &str = "# intcode transcript";
+const VERSION: u8 = 1;
+const VALUES_PER_LINE: usize = 16;
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum TranscriptEvent<C = isize> {
+    Input(C),
+    Output(C),
+    // the machine needed input and there was none (a resume point)
+    Wait,
+    Halt,
+}
+
+impl<C: Display> Display for TranscriptEvent<C> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            TranscriptEvent::Input(value) => write!(f, "input {}", value),
+            TranscriptEvent::Output(value) => write!(f, "output {}", value),
+            TranscriptEvent::Wait => write!(f, "wait for input"),
+            TranscriptEvent::Halt => write!(f, "halt"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
+pub struct Transcript<C = isize> {
+    pub events: Vec<TranscriptEvent<C>>,
+}
+
+impl<C: Cell> Transcript<C> {
+    pub fn new() -> Transcript<C> {
+        Transcript { events: Vec::new() }
+    }
+
+    // indices of the `Wait` events
+    pub fn resume_points(&self) -> Vec<usize> {
+        self.events
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| **event == TranscriptEvent::Wait)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    // The transcript up to and including the nth resume point (zero-based), e.g. to replay the
+    // first commands of a session and continue by hand. `None` if there are not that many.
+    pub fn until_resume_point(&self, n: usize) -> Option<Transcript<C>> {
+        let end = *self.resume_points().get(n)?;
+        Some(Transcript {
+            events: self.events[..=end].to_vec(),
+        })
+    }
+
+    pub fn inputs(&self) -> Vec<C> {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                TranscriptEvent::Input(value) => Some(value.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn outputs(&self) -> Vec<C> {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                TranscriptEvent::Output(value) => Some(value.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // a blocked or halted machine is recorded only once
+    fn push(&mut self, event: TranscriptEvent<C>) {
+        let repeated = matches!(
+            (&event, self.events.last()),
+            (TranscriptEvent::Wait, Some(TranscriptEvent::Wait))
+                | (TranscriptEvent::Halt, Some(TranscriptEvent::Halt))
+        );
+        if !repeated {
+            self.events.push(event);
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::with_capacity(self.events.len() * 4 + 64);
+        write_text_header(&mut text, TEXT_HEADER, VERSION);
+        let mut i = 0;
+        while i < self.events.len() {
+            let (key, values) = match &self.events[i] {
+                TranscriptEvent::Wait => {
+                    let _ = writeln!(text, "wait");
+                    i += 1;
+                    continue;
+                }
+                TranscriptEvent::Halt => {
+                    let _ = writeln!(text, "halt");
+                    i += 1;
+                    continue;
+                }
+                TranscriptEvent::Input(_) => ("in", self.input_run(i)),
+                TranscriptEvent::Output(_) => ("out", self.output_run(i)),
+            };
+            let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+            let _ = writeln!(text, "{}: {}", key, values.join(","));
+            i += values.len();
+        }
+        text
+    }
+
+    // the inputs starting at `start`, at most one line
+    fn input_run(&self, start: usize) -> Vec<&C> {
+        self.events[start..]
+            .iter()
+            .map_while(|event| match event {
+                TranscriptEvent::Input(value) => Some(value),
+                _ => None,
+            })
+            .take(VALUES_PER_LINE)
+            .collect()
+    }
+
+    // the outputs starting at `start`, at most one line
+    fn output_run(&self, start: usize) -> Vec<&C> {
+        self.events[start..]
+            .iter()
+            .map_while(|event| match event {
+                TranscriptEvent::Output(value) => Some(value),
+                _ => None,
+            })
+            .take(VALUES_PER_LINE)
+            .collect()
+    }
+
+    pub fn from_text(text: &str) -> Result<Transcript<C>, DecodeError> {
+        let mut transcript = Transcript::new();
+        for (line_nr, line) in text_lines(text, TEXT_HEADER, VERSION)? {
+            let error = |message: String| line_error(text, line_nr, message);
+            match line {
+                "wait" => transcript.events.push(TranscriptEvent::Wait),
+                "halt" => transcript.events.push(TranscriptEvent::Halt),
+                _ => {
+                    let (key, values) = line
+                        .split_once(':')
+                        .ok_or_else(|| error(format!("unknown event '{}'", line)))?;
+                    let event: fn(C) -> TranscriptEvent<C> = match key.trim() {
+                        "in" => TranscriptEvent::Input,
+                        "out" => TranscriptEvent::Output,
+                        key => return Err(error(format!("unknown event '{}'", key))),
+                    };
+                    for value in values.split(',') {
+                        let value = C::from_token(value.trim()).map_err(error)?;
+                        transcript.events.push(event(value));
+                    }
+                }
+            }
+        }
+        Ok(transcript)
+    }
+}
+
+// Runs a machine and records everything it reads and writes
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Recorder<M: Memory = DenseMemory> {
+    state: State<M>,
+    transcript: Transcript<M::Cell>,
+}
+
+impl<M: Memory> Recorder<M> {
+    pub fn new(state: State<M>) -> Recorder<M> {
+        Recorder {
+            state,
+            transcript: Transcript::new(),
+        }
+    }
+
+    // Continue recording a session, e.g. after `replay` brought `state` to the end of
+    // `transcript`
+    pub fn with_transcript(state: State<M>, transcript: Transcript<M::Cell>) -> Recorder<M> {
+        Recorder { state, transcript }
+    }
+
+    pub fn state(&self) -> &State<M> {
+        &self.state
+    }
+
+    pub fn transcript(&self) -> &Transcript<M::Cell> {
+        &self.transcript
+    }
+
+    pub fn into_parts(self) -> (State<M>, Transcript<M::Cell>) {
+        (self.state, self.transcript)
+    }
+
+    // Like `run`, but recorded
+    pub fn run<I, O>(&mut self, input: &mut I, output: &mut O) -> Result<ReturnStatus, IntcodeError>
+    where
+        I: IntcodeInput<M::Cell> + ?Sized,
+        O: IntcodeOutput<M::Cell> + ?Sized,
+    {
+        self.run_limited(input, output, &Limits::unlimited())
+    }
+
+    // Like `run_limited`, but recorded. Errors are not part of the transcript: a replay of the
+    // transcript ends where the error occured.
+    pub fn run_limited<I, O>(
+        &mut self,
+        input: &mut I,
+        output: &mut O,
+        limits: &Limits,
+    ) -> Result<ReturnStatus, IntcodeError>
+    where
+        I: IntcodeInput<M::Cell> + ?Sized,
+        O: IntcodeOutput<M::Cell> + ?Sized,
+    {
+        run_steps(
+            self,
+            output,
+            limits,
+            |recorder| at_halt(&recorder.state),
+            |recorder| {
+                let event = recorder.state.step_with(input)?.event;
+                match &event {
+                    StepEvent::Continue => (),
+                    StepEvent::Input(value) => recorder
+                        .transcript
+                        .push(TranscriptEvent::Input(value.clone())),
+                    StepEvent::Output(value) => recorder
+                        .transcript
+                        .push(TranscriptEvent::Output(value.clone())),
+                    StepEvent::InputRequired => recorder.transcript.push(TranscriptEvent::Wait),
+                    StepEvent::Halt => recorder.transcript.push(TranscriptEvent::Halt),
+                }
+                Ok(event)
+            },
+        )
+    }
+}
+
+// The first event of a replay that does not match the transcript
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct Divergence<C = isize> {
+    // index of the event in the transcript
+    pub position: usize,
+    pub expected: TranscriptEvent<C>,
+    // what the machine did instead: it can only write other output, wait for input it does not
+    // get, halt or fail
+    pub actual: Result<TranscriptEvent<C>, IntcodeError>,
+}
+
+impl<C: Display> Display for Divergence<C> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "Replay diverged at event {}: expected {}, but ",
+            self.position, self.expected
+        )?;
+        match &self.actual {
+            Ok(TranscriptEvent::Wait) => write!(f, "the program waits for input"),
+            Ok(TranscriptEvent::Halt) => write!(f, "the program halted"),
+            Ok(event) => write!(f, "got {}", event),
+            Err(error) => write!(f, "the program failed: {}", error),
+        }
+    }
+}
+
+impl<C: Display + std::fmt::Debug> Error for Divergence<C> {}
+
+impl<C: Display> From<Divergence<C>> for String {
+    fn from(divergence: Divergence<C>) -> String {
+        divergence.to_string()
+    }
+}
+
+// Run the program in `state` with the input of the transcript and check that it behaves as
+// recorded. Stops at the end of the transcript, so the state can be used to continue the
+// session; after a divergence, the state is left at the diverging instruction.
+pub fn replay<M: Memory>(
+    state: &mut State<M>,
+    transcript: &Transcript<M::Cell>,
+) -> Result<(), Divergence<M::Cell>> {
+    for (position, expected) in transcript.events.iter().enumerate() {
+        // input is only available where the transcript has some
+        let mut input = match expected {
+            TranscriptEvent::Input(value) => Some(value.clone()),
+            _ => None,
+        };
+        let actual = loop {
+            match state.step_with(&mut input) {
+                Ok(step) => match step.event {
+                    StepEvent::Continue => (),
+                    StepEvent::Input(value) => break Ok(TranscriptEvent::Input(value)),
+                    StepEvent::Output(value) => break Ok(TranscriptEvent::Output(value)),
+                    StepEvent::InputRequired => break Ok(TranscriptEvent::Wait),
+                    StepEvent::Halt => break Ok(TranscriptEvent::Halt),
+                },
+                Err(error) => break Err(error),
+            }
+        };
+        if actual.as_ref() != Ok(expected) {
+            return Err(Divergence {
+                position,
+                expected: expected.clone(),
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assemble;
+    use std::collections::VecDeque;
+
+    // outputs 42, then reads numbers and outputs their doubles until it reads 0
+    const DOUBLER: &str = "
+                OUT #42
+        loop:   IN [x]
+                JZ [x], #end
+                MUL [x], #2, [y]
+                OUT [y]
+                JNZ #1, #loop
+        end:    HLT
+        x:      DATA 0
+        y:      DATA 0
+    ";
+
+    fn doubler() -> State {
+        State::new(assemble(DOUBLER).expect("Expected valid program"))
+    }
+
+    fn record_session() -> Transcript {
+        let mut recorder = Recorder::new(doubler());
+        let mut output: Vec<isize> = Vec::new();
+        let mut input: VecDeque<isize> = VecDeque::new();
+        let _ = recorder.run(&mut input, &mut output);
+        input.extend(vec![3, 5]);
+        let _ = recorder.run(&mut input, &mut output);
+        input.push_back(0);
+        let _ = recorder.run(&mut input, &mut output);
+        recorder.into_parts().1
+    }
+
+    #[test]
+    fn recorded_session_round_trips_through_text() {
+        // given
+        let transcript = record_session();
+
+        // when
+        let text = transcript.to_text();
+        let parsed = Transcript::from_text(&text);
+
+        // then
+        assert_eq!(
+            text,
+            "# intcode transcript\nversion: 1\nout: 42\nwait\nin: 3\nout: 6\nin: 5\nout: 10\n\
+             wait\nin: 0\nhalt\n"
+        );
+        assert_eq!(parsed, Ok(transcript.clone()));
+        assert_eq!(transcript.resume_points(), vec![1, 6]);
+        assert_eq!(transcript.inputs(), vec![3, 5, 0]);
+        assert_eq!(transcript.outputs(), vec![42, 6, 10]);
+    }
+
+    #[test]
+    fn replay_reproduces_the_session_and_can_be_continued() {
+        // given
+        let transcript = record_session();
+        let first_part = transcript
+            .until_resume_point(1)
+            .expect("Expected resume point");
+        let mut state = doubler();
+        let mut output: Vec<isize> = Vec::new();
+
+        // when
+        let result = replay(&mut state, &first_part);
+        let status = crate::run(&mut state, &mut Some(7), &mut output);
+
+        // then
+        assert_eq!(result, Ok(()));
+        assert_eq!(replay(&mut doubler(), &transcript), Ok(()));
+        assert_eq!(status, Ok(ReturnStatus::Wait));
+        assert_eq!(output, vec![14]);
+    }
+
+    #[test]
+    fn replay_reports_first_divergence() {
+        // given
+        let mut transcript = record_session();
+        transcript.events[5] = TranscriptEvent::Output(7);
+        let mut state = doubler();
+
+        // when
+        let result = replay(&mut state, &transcript);
+
+        // then
+        let divergence = result.expect_err("Expected divergence");
+        assert_eq!(divergence.position, 5);
+        assert_eq!(divergence.actual, Ok(TranscriptEvent::Output(10)));
+        assert_eq!(
+            divergence.to_string(),
+            "Replay diverged at event 5: expected output 7, but got output 10"
+        );
+    }
+}